@@ -0,0 +1,188 @@
+use crate::core::request::ThrusterRequest;
+
+/// A predicate on a request that must hold for a guarded route to handle it. See
+/// `App::route_with_guard`.
+pub type Guard<R> = Box<dyn Fn(&R) -> bool + Send + Sync>;
+
+/// Matches requests that have the given header with exactly the given value, ignoring case.
+pub fn header<R: ThrusterRequest>(name: &str, value: &str) -> impl Fn(&R) -> bool + Send + Sync {
+    let name = name.to_owned();
+    let value = value.to_owned();
+
+    move |request: &R| {
+        request
+            .header(&name)
+            .map(|header| header.eq_ignore_ascii_case(&value))
+            .unwrap_or(false)
+    }
+}
+
+/// Matches requests whose `Accept` header lists the given media type, e.g.
+/// `application/vnd.example.v2+json`. Wildcards and quality values in the header aren't
+/// considered.
+pub fn accepts<R: ThrusterRequest>(media_type: &str) -> impl Fn(&R) -> bool + Send + Sync {
+    let media_type = media_type.to_owned();
+
+    move |request: &R| {
+        request
+            .header("accept")
+            .map(|accept| {
+                accept
+                    .split(',')
+                    .any(|accepted| media_type_of(accepted).eq_ignore_ascii_case(&media_type))
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Matches requests whose `Content-Type` header has the given media type, ignoring any
+/// parameters such as the charset.
+pub fn content_type<R: ThrusterRequest>(media_type: &str) -> impl Fn(&R) -> bool + Send + Sync {
+    let media_type = media_type.to_owned();
+
+    move |request: &R| {
+        request
+            .header("content-type")
+            .map(|content_type| media_type_of(content_type).eq_ignore_ascii_case(&media_type))
+            .unwrap_or(false)
+    }
+}
+
+/// Matches requests whose query string has the given param with the given value.
+pub fn query<R: ThrusterRequest>(name: &str, value: &str) -> impl Fn(&R) -> bool + Send + Sync {
+    let name = name.to_owned();
+    let value = value.to_owned();
+
+    move |request: &R| {
+        let path = request.path();
+
+        path.split_once('?')
+            .map(|(_, query)| {
+                query.split('&').any(|pair| {
+                    let mut pair = pair.splitn(2, '=');
+
+                    pair.next() == Some(name.as_str()) && pair.next().unwrap_or("") == value
+                })
+            })
+            .unwrap_or(false)
+    }
+}
+
+fn media_type_of(value: &str) -> &str {
+    value.split(';').next().unwrap_or("").trim()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestRequest {
+        path: &'static str,
+        headers: Vec<(&'static str, &'static str)>,
+    }
+
+    impl TestRequest {
+        fn new(path: &'static str, headers: &[(&'static str, &'static str)]) -> Self {
+            TestRequest {
+                path,
+                headers: headers.to_vec(),
+            }
+        }
+    }
+
+    impl ThrusterRequest for TestRequest {
+        fn method(&self) -> &str {
+            "GET"
+        }
+
+        fn path(&self) -> String {
+            self.path.to_owned()
+        }
+
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| *value)
+        }
+    }
+
+    #[test]
+    fn it_should_match_headers_ignoring_case() {
+        let guard = header::<TestRequest>("X-Api-Version", "v2");
+
+        assert!(guard(&TestRequest::new("/", &[("x-api-version", "V2")])));
+        assert!(!guard(&TestRequest::new("/", &[("x-api-version", "v1")])));
+        assert!(!guard(&TestRequest::new(
+            "/",
+            &[("x-api-version", "v2, v1")]
+        )));
+        assert!(!guard(&TestRequest::new("/", &[])));
+    }
+
+    #[test]
+    fn it_should_match_accepted_media_types() {
+        let guard = accepts::<TestRequest>("application/json");
+
+        assert!(guard(&TestRequest::new(
+            "/",
+            &[("accept", "text/html, Application/JSON")]
+        )));
+        assert!(guard(&TestRequest::new(
+            "/",
+            &[("accept", "text/html;q=1.0, application/json;q=0.5")]
+        )));
+        assert!(!guard(&TestRequest::new("/", &[("accept", "text/html")])));
+        assert!(!guard(&TestRequest::new("/", &[])));
+    }
+
+    #[test]
+    fn it_should_not_match_accepted_wildcards() {
+        let guard = accepts::<TestRequest>("application/json");
+
+        assert!(!guard(&TestRequest::new("/", &[("accept", "*/*")])));
+        assert!(!guard(&TestRequest::new(
+            "/",
+            &[("accept", "application/*")]
+        )));
+    }
+
+    #[test]
+    fn it_should_match_content_types_ignoring_params() {
+        let guard = content_type::<TestRequest>("application/json");
+
+        assert!(guard(&TestRequest::new(
+            "/",
+            &[("content-type", "application/json")]
+        )));
+        assert!(guard(&TestRequest::new(
+            "/",
+            &[("content-type", "application/json; charset=utf-8")]
+        )));
+        assert!(guard(&TestRequest::new(
+            "/",
+            &[("content-type", "Application/JSON;charset=utf-8")]
+        )));
+        assert!(!guard(&TestRequest::new(
+            "/",
+            &[("content-type", "application/json-patch+json")]
+        )));
+        assert!(!guard(&TestRequest::new("/", &[])));
+    }
+
+    #[test]
+    fn it_should_match_query_params() {
+        let guard = query::<TestRequest>("format", "csv");
+
+        assert!(guard(&TestRequest::new("/users?format=csv", &[])));
+        assert!(guard(&TestRequest::new("/users?page=2&format=csv", &[])));
+        assert!(!guard(&TestRequest::new("/users?format=json", &[])));
+        assert!(!guard(&TestRequest::new("/users?format=csv2", &[])));
+        assert!(!guard(&TestRequest::new("/users", &[])));
+
+        let guard = query::<TestRequest>("debug", "");
+
+        assert!(guard(&TestRequest::new("/users?debug", &[])));
+        assert!(guard(&TestRequest::new("/users?debug=", &[])));
+    }
+}
@@ -1,3 +1,11 @@
+pub mod guards;
+#[cfg(feature = "hyper_server")]
+mod mounted_service;
+mod named_routes;
+mod path_normalization;
+mod route_conflicts;
+mod scope;
+mod swappable_app;
 mod thruster_app;
 
 #[cfg(not(feature = "hyper_server"))]
@@ -13,6 +21,11 @@ pub mod testing_async {
 
 use async_trait::async_trait;
 pub use httparse::Header;
+pub use named_routes::NamedRoutes;
+pub use path_normalization::{DotSegments, PathNormalization};
+pub use route_conflicts::RouteConflict;
+pub use scope::Scope;
+pub use swappable_app::SwappableApp;
 pub use thruster_app::*;
 
 use self::testing_async::TestResponse;
@@ -0,0 +1,202 @@
+use bytes::Buf;
+use futures::future::poll_fn;
+use hyper::body::HttpBody;
+use hyper::service::Service;
+use hyper::{Body, Request, Response};
+use std::error::Error;
+use std::io;
+
+use crate::app::App;
+use crate::context::hyper_request::HyperRequest;
+use crate::core::context::Context;
+use crate::ReusableBoxFuture;
+
+impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + Send>
+    App<HyperRequest, T, S>
+{
+    /// Mount a `tower::Service`, such as a tonic gRPC server, a GraphQL server or a static file
+    /// service, to handle every request whose path is under the given prefix. The raw hyper
+    /// request is passed to the service as it was received, without the prefix being stripped,
+    /// and the service's response is sent back as is, trailers included.
+    ///
+    /// Mounted services are checked before any of the app's routes or middleware, and they're
+    /// checked in the order they were mounted. The prefix is matched against the path after the
+    /// app's `PathNormalization`, like routes are, though the service gets the path as sent.
+    ///
+    /// Mounted services are only used by the hyper servers, since they're run from
+    /// `match_and_resolve`.
+    pub fn mount_service<Svc, B>(mut self, prefix: &str, service: Svc) -> Self
+    where
+        Svc: Service<Request<Body>, Response = Response<B>> + Clone + Send + Sync + 'static,
+        Svc::Future: Send + 'static,
+        Svc::Error: Into<Box<dyn Error + Send + Sync>>,
+        B: HttpBody + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn Error + Send + Sync>>,
+    {
+        self.mounted_services.push((
+            prefix.to_owned(),
+            Box::new(move |request: HyperRequest| {
+                let mut service = service.clone();
+
+                ReusableBoxFuture::new(async move {
+                    poll_fn(|cx| service.poll_ready(cx))
+                        .await
+                        .map_err(into_io_error)?;
+                    let response = service.call(request.request).await.map_err(into_io_error)?;
+
+                    Ok(response.map(into_hyper_body))
+                })
+            }),
+        ));
+
+        self
+    }
+}
+
+fn into_io_error(e: impl Into<Box<dyn Error + Send + Sync>>) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Forwards the data and trailers of a body into a hyper `Body`, which gRPC needs for its
+/// `grpc-status` trailer.
+fn into_hyper_body<B>(body: B) -> Body
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let (mut sender, hyper_body) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut body = Box::pin(body);
+
+        loop {
+            let data = match body.data().await {
+                Some(Ok(mut data)) => data.copy_to_bytes(data.remaining()),
+                Some(Err(_)) => return sender.abort(),
+                None => break,
+            };
+
+            if sender.send_data(data).await.is_err() {
+                return;
+            }
+        }
+
+        let trailers = match body.trailers().await {
+            Ok(trailers) => trailers,
+            Err(_) => return sender.abort(),
+        };
+
+        if let Some(trailers) = trailers {
+            let _ = sender.send_trailers(trailers).await;
+        }
+    });
+
+    hyper_body
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::service::service_fn;
+    use std::convert::Infallible;
+
+    use crate::app::{DotSegments, PathNormalization};
+    use crate::context::basic_hyper_context::{generate_context, BasicHyperContext};
+    use crate::core::errors::ThrusterError;
+    use crate::parser::middleware_traits::{MiddlewareTuple, NextFn};
+    use crate::pinbox;
+    use crate::ReusableBoxFuture;
+    use crate::Testable;
+
+    use super::*;
+
+    async fn thruster_body(
+        mut context: BasicHyperContext,
+        _next: NextFn<BasicHyperContext>,
+    ) -> Result<BasicHyperContext, ThrusterError<BasicHyperContext>> {
+        context.body("thruster");
+        Ok(context)
+    }
+
+    #[test]
+    fn it_should_forward_requests_under_the_prefix_to_a_mounted_service() {
+        let service = service_fn(|request: Request<Body>| async move {
+            Ok::<_, Infallible>(Response::new(Body::from(format!(
+                "service {}",
+                request.uri()
+            ))))
+        });
+        let app = App::<HyperRequest, BasicHyperContext, ()>::create(generate_context, ())
+            .get(
+                "/*",
+                MiddlewareTuple::A(pinbox!(BasicHyperContext, thruster_body)),
+            )
+            .mount_service("/grpc", service)
+            .commit();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let body = |path: &'static str| {
+                    let app = &app;
+
+                    async move {
+                        Testable::get(app, path, vec![])
+                            .await
+                            .unwrap()
+                            .body_string()
+                    }
+                };
+
+                assert_eq!(body("/grpc").await, "service /grpc");
+                assert_eq!(body("/grpc/a.B/C?d").await, "service /grpc/a.B/C?d");
+                assert_eq!(body("/grpcs").await, "thruster");
+                assert_eq!(body("/other").await, "thruster");
+            });
+    }
+
+    #[test]
+    fn it_should_match_mounted_services_against_the_normalized_path() {
+        let service = service_fn(|request: Request<Body>| async move {
+            Ok::<_, Infallible>(Response::new(Body::from(format!(
+                "service {}",
+                request.uri()
+            ))))
+        });
+        let app = App::<HyperRequest, BasicHyperContext, ()>::create(generate_context, ())
+            .get(
+                "/*",
+                MiddlewareTuple::A(pinbox!(BasicHyperContext, thruster_body)),
+            )
+            .mount_service("/api/grpc", service)
+            .set_path_normalization(PathNormalization {
+                decode_params: false,
+                merge_slashes: true,
+                dot_segments: DotSegments::Resolve,
+            })
+            .commit();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let body = |path: &'static str| {
+                    let app = &app;
+
+                    async move {
+                        Testable::get(app, path, vec![])
+                            .await
+                            .unwrap()
+                            .body_string()
+                    }
+                };
+
+                assert_eq!(body("/api//grpc/x").await, "service /api//grpc/x");
+                assert_eq!(
+                    body("/other/../api/grpc").await,
+                    "service /other/../api/grpc"
+                );
+                assert_eq!(body("/api/grpc/../admin").await, "thruster");
+            });
+    }
+}
@@ -0,0 +1,179 @@
+use fnv::FnvHashMap;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// The characters that are percent-encoded when a param is placed into a path segment.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}')
+    .add(b'/');
+
+/// The characters that are percent-encoded when a param is placed into a catch-all, which
+/// can span several path segments.
+const CATCH_ALL: &AsciiSet = &SEGMENT.remove(b'/');
+
+/// The route patterns of an `App` that were given names at registration, e.g. via `get_named`.
+/// This is cloneable so that it can be kept around, e.g. in the app's state, for building urls
+/// from within middleware.
+#[derive(Clone, Debug, Default)]
+pub struct NamedRoutes {
+    /// The method and path pattern of each route, keyed by name.
+    routes: FnvHashMap<String, (String, String)>,
+}
+
+impl NamedRoutes {
+    /// Adds a route pattern with the given name, replacing any existing route with that name.
+    pub fn add(&mut self, name: &str, method: &str, path: &str) {
+        self.routes
+            .insert(name.to_owned(), (method.to_owned(), path.to_owned()));
+    }
+
+    /// Adds all of the routes from another set of named routes, prefixing each of their paths.
+    pub fn add_with_prefix(&mut self, prefix: &str, named_routes: NamedRoutes) {
+        for (name, (method, path)) in named_routes.routes {
+            self.routes
+                .insert(name, (method, join_paths(prefix, &path)));
+        }
+    }
+
+    /// Gets the route pattern with the given name, e.g. `/users/:id`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.routes.get(name).map(|(_, path)| path.as_str())
+    }
+
+    /// Gets the name of the route with the given method and path pattern, if it has one.
+    pub fn name_for(&self, method: &str, path: &str) -> Option<&str> {
+        let path = join_paths("/", path);
+
+        self.routes
+            .iter()
+            .find(|(_, (route_method, route_path))| {
+                route_method == method
+                    && join_paths("/", route_path).trim_end_matches('/')
+                        == path.trim_end_matches('/')
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Builds a url for the named route, filling in its params and catch-alls from the given
+    /// pairs of names and values. Returns `None` if there is no route with that name, if any of
+    /// its params are missing, if it has an unnamed `*` wildcard, which can't be given a value, or
+    /// if a value would make a `.` or `..` segment, which clients resolve rather than send.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let (_, path) = self.routes.get(name)?;
+        let mut url_pieces = vec![];
+
+        for piece in path.split('/') {
+            let (param, encode_set) = match piece.chars().next() {
+                Some(':') => (piece[1..].split('<').next().unwrap_or_default(), SEGMENT),
+                Some('*') if piece.len() > 1 => (&piece[1..], CATCH_ALL),
+                Some('*') => return None,
+                _ => {
+                    url_pieces.push(piece.to_owned());
+                    continue;
+                }
+            };
+
+            let (_, value) = params.iter().find(|(key, _)| *key == param)?;
+            let value = utf8_percent_encode(value, encode_set).to_string();
+
+            if value
+                .split('/')
+                .any(|segment| segment == "." || segment == "..")
+            {
+                return None;
+            }
+
+            url_pieces.push(value);
+        }
+
+        Some(url_pieces.join("/"))
+    }
+}
+
+/// Joins a prefix and a path the way `App::router` mounts them.
+pub(crate) fn join_paths(prefix: &str, path: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    let path = path.trim_start_matches('/');
+
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, true) => "/".to_owned(),
+        (false, true) => prefix.to_owned(),
+        _ => format!("{}/{}", prefix, path),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_fill_in_params_and_catch_alls() {
+        let mut named_routes = NamedRoutes::default();
+        named_routes.add("user_show", "GET", "/users/:id<u64>/files/*rest");
+
+        assert_eq!(
+            named_routes
+                .url_for("user_show", &[("id", "42"), ("rest", "a b/c.txt")])
+                .unwrap(),
+            "/users/42/files/a%20b/c.txt"
+        );
+        assert!(named_routes.url_for("user_show", &[("id", "42")]).is_none());
+        assert!(named_routes.url_for("missing", &[]).is_none());
+    }
+
+    #[test]
+    fn it_should_not_fill_in_unnamed_wildcards() {
+        let mut named_routes = NamedRoutes::default();
+        named_routes.add("files", "GET", "/files/*");
+
+        assert!(named_routes.url_for("files", &[("*", "a.txt")]).is_none());
+    }
+
+    #[test]
+    fn it_should_not_fill_in_dot_segments() {
+        let mut named_routes = NamedRoutes::default();
+        named_routes.add("user_show", "GET", "/users/:id/files/*rest");
+
+        assert!(named_routes
+            .url_for("user_show", &[("id", ".."), ("rest", "a.txt")])
+            .is_none());
+        assert!(named_routes
+            .url_for("user_show", &[("id", "."), ("rest", "a.txt")])
+            .is_none());
+        assert!(named_routes
+            .url_for("user_show", &[("id", "1"), ("rest", "a/../../admin")])
+            .is_none());
+        assert_eq!(
+            named_routes
+                .url_for("user_show", &[("id", "..."), ("rest", "a/.b/c..txt")])
+                .unwrap(),
+            "/users/.../files/a/.b/c..txt"
+        );
+    }
+
+    #[test]
+    fn it_should_prefix_added_routes() {
+        let mut inner = NamedRoutes::default();
+        inner.add("user_show", "GET", "/users/:id");
+        inner.add("users", "GET", "/");
+
+        let mut named_routes = NamedRoutes::default();
+        named_routes.add_with_prefix("/api/", inner);
+
+        assert_eq!(
+            named_routes.url_for("user_show", &[("id", "a/b")]).unwrap(),
+            "/api/users/a%2Fb"
+        );
+        assert_eq!(named_routes.url_for("users", &[]).unwrap(), "/api");
+        assert_eq!(named_routes.name_for("GET", "/api/"), Some("users"));
+        assert_eq!(named_routes.name_for("POST", "/api"), None);
+    }
+}
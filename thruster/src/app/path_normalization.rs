@@ -0,0 +1,129 @@
+use percent_encoding::percent_decode_str;
+
+use crate::parser::tree::Params;
+
+/// What to do with `.` and `..` segments in request paths.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DotSegments {
+    /// Match them literally, like any other segment.
+    #[default]
+    Keep,
+    /// Resolve them the way a browser would, e.g. `/a/./b/../c` becomes `/a/c`. A `..` at the
+    /// root is dropped.
+    Resolve,
+    /// Respond with a `400` to any request with a dot segment in its path, including
+    /// percent-encoded ones like `%2e%2e`.
+    Reject,
+}
+
+/// How request paths are normalized before they're matched against an `App`'s routes. None of
+/// the normalization is done by default, so that paths are matched exactly as they're sent.
+/// Query strings are left alone either way.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PathNormalization {
+    /// Percent-decode the values of params and catch-alls once a route is matched, e.g. so that
+    /// `/users/j%C3%B6rg` gives `jörg` for `/users/:name`. Values that don't decode to valid
+    /// UTF-8 are left as they were.
+    pub decode_params: bool,
+    /// Merge runs of `/` into one, so that `/a//b` matches `/a/b`.
+    pub merge_slashes: bool,
+    /// What to do with `.` and `..` segments.
+    pub dot_segments: DotSegments,
+}
+
+impl PathNormalization {
+    /// Normalizes the given path, returning `None` if it should be rejected.
+    pub(crate) fn normalize(&self, path: String) -> Option<String> {
+        if !self.merge_slashes && self.dot_segments == DotSegments::Keep {
+            return Some(path);
+        }
+
+        let (path, query) = path.split_at(path.find('?').unwrap_or(path.len()));
+        let pieces = path.split('/').collect::<Vec<&str>>();
+        let last = pieces.len() - 1;
+        let mut segments = vec![];
+
+        for (i, piece) in pieces.iter().enumerate().skip(1) {
+            let dot_segment = match percent_decode_str(piece).decode_utf8() {
+                Ok(decoded) if decoded == "." => Some(false),
+                Ok(decoded) if decoded == ".." => Some(true),
+                _ => None,
+            };
+
+            match (dot_segment, self.dot_segments) {
+                (Some(_), DotSegments::Reject) => return None,
+                (Some(parent), DotSegments::Resolve) => {
+                    if parent {
+                        segments.pop();
+                    }
+
+                    // A dot segment at the end still refers to a directory, e.g. `/a/b/..` is `/a/`
+                    if i == last {
+                        segments.push("");
+                    }
+                }
+                _ if piece.is_empty() && self.merge_slashes && i != last => (),
+                _ => segments.push(piece),
+            }
+        }
+
+        Some(format!("{}/{}{}", pieces[0], segments.join("/"), query))
+    }
+
+    /// Percent-decodes the values of the given params, if enabled.
+    pub(crate) fn decode_params(&self, params: &mut Params) {
+        if !self.decode_params {
+            return;
+        }
+
+        for param in params.iter_mut() {
+            if let Ok(decoded) = percent_decode_str(&param.param).decode_utf8() {
+                param.param = decoded.into_owned();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn normalize(normalization: PathNormalization, path: &str) -> Option<String> {
+        normalization.normalize(path.to_owned())
+    }
+
+    #[test]
+    fn it_should_merge_slashes() {
+        let normalization = PathNormalization {
+            merge_slashes: true,
+            ..PathNormalization::default()
+        };
+
+        assert_eq!(normalize(normalization, "/a//b").unwrap(), "/a/b");
+        assert_eq!(
+            normalize(normalization, "//a///b//?c=//").unwrap(),
+            "/a/b/?c=//"
+        );
+        assert_eq!(normalize(normalization, "/").unwrap(), "/");
+        assert_eq!(normalize(normalization, "/a/./b").unwrap(), "/a/./b");
+    }
+
+    #[test]
+    fn it_should_resolve_or_reject_dot_segments() {
+        let resolve = PathNormalization {
+            dot_segments: DotSegments::Resolve,
+            ..PathNormalization::default()
+        };
+        let reject = PathNormalization {
+            dot_segments: DotSegments::Reject,
+            ..PathNormalization::default()
+        };
+
+        assert_eq!(normalize(resolve, "/a/./b/../c?d").unwrap(), "/a/c?d");
+        assert_eq!(normalize(resolve, "/../a/b/..").unwrap(), "/a/");
+        assert_eq!(normalize(resolve, "/a/%2E%2E").unwrap(), "/");
+        assert_eq!(normalize(reject, "/a/b").unwrap(), "/a/b");
+        assert!(normalize(reject, "/a/../b").is_none());
+        assert!(normalize(reject, "/a/%2e").is_none());
+    }
+}
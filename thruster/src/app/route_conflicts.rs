@@ -0,0 +1,299 @@
+use std::fmt;
+
+/// A problem with the routes registered on an `App`, found by comparing routes of the same
+/// method with each other in the order they were registered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouteConflict {
+    /// The same route was registered more than once, so the later registration either replaced
+    /// or, if it came from a `router`, was chained onto the earlier one.
+    Duplicate { method: String, path: String },
+    /// Two routes have params at the same level of the route tree with different names, e.g.
+    /// `/a/:id` and `/a/:name/b`. Only one of the names is used for both routes.
+    ParamName {
+        method: String,
+        path: String,
+        other_path: String,
+    },
+    /// A route was made unreachable by a later route with a wildcard in the same place, e.g.
+    /// `/a/:id` and `/a/*`.
+    Shadowed {
+        method: String,
+        path: String,
+        by: String,
+    },
+}
+
+impl fmt::Display for RouteConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteConflict::Duplicate { method, path } => {
+                write!(f, "{} {} is registered more than once", method, path)
+            }
+            RouteConflict::ParamName {
+                method,
+                path,
+                other_path,
+            } => write!(
+                f,
+                "{} {} names a param differently than {} at the same level",
+                method, path, other_path
+            ),
+            RouteConflict::Shadowed { method, path, by } => {
+                write!(f, "{} {} is shadowed by {}", method, path, by)
+            }
+        }
+    }
+}
+
+/// A route as it was registered on an `App`, for finding conflicts on commit.
+pub(crate) struct RegisteredRoute {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    /// Whether the route was added via `route_with_guard`. Guarded routes are meant to share
+    /// their path with other routes, so they're only checked for params named differently than
+    /// those of other routes at the same level, which is as confusing for them as for the rest.
+    pub(crate) guarded: bool,
+}
+
+/// A piece of a route, as far as which node of the route tree it ends up in.
+#[derive(PartialEq)]
+enum Piece<'a> {
+    Literal(&'a str),
+    /// A `:param`, or a `*` wildcard if it has no name. Both share the same node.
+    Param(Option<&'a str>),
+    Constrained(&'a str),
+    CatchAll(&'a str),
+}
+
+impl<'a> Piece<'a> {
+    fn parse(piece: &'a str) -> Piece<'a> {
+        if piece.starts_with(':') && piece.ends_with('>') && piece.contains('<') {
+            Piece::Constrained(piece)
+        } else if let Some(name) = piece.strip_prefix(':') {
+            Piece::Param(Some(name))
+        } else if piece == "*" {
+            Piece::Param(None)
+        } else if let Some(name) = piece.strip_prefix('*') {
+            Piece::CatchAll(name)
+        } else {
+            Piece::Literal(piece)
+        }
+    }
+
+    fn same_node(&self, other: &Piece<'_>, case_insensitive: bool) -> bool {
+        match (self, other) {
+            (Piece::Param(_), Piece::Param(_)) | (Piece::CatchAll(_), Piece::CatchAll(_)) => true,
+            (a, b) => a.same_piece(b, case_insensitive),
+        }
+    }
+
+    /// Whether the pieces are the same, comparing literals the way the route tree matches them.
+    fn same_piece(&self, other: &Piece<'_>, case_insensitive: bool) -> bool {
+        match (self, other) {
+            (Piece::Literal(a), Piece::Literal(b)) if case_insensitive => a.eq_ignore_ascii_case(b),
+            (a, b) => a == b,
+        }
+    }
+
+    fn name_conflicts(&self, other: &Piece<'_>) -> bool {
+        match (self, other) {
+            (Piece::Param(Some(a)), Piece::Param(Some(b))) => a != b,
+            (Piece::CatchAll(a), Piece::CatchAll(b)) => a != b,
+            _ => false,
+        }
+    }
+}
+
+/// Splits a route into the pieces that the route tree would, which outside of strict mode
+/// ignores empty pieces, so that `/a` and `/a/` are the same route.
+fn pieces(path: &str, strict_mode: bool) -> Vec<Piece<'_>> {
+    let mut pieces = vec![];
+
+    for piece in path.trim_start_matches('/').split('/') {
+        if piece.is_empty() && !strict_mode {
+            continue;
+        }
+
+        let piece = Piece::parse(piece);
+        let is_catch_all = matches!(piece, Piece::CatchAll(_));
+        pieces.push(piece);
+
+        if is_catch_all {
+            break;
+        }
+    }
+
+    pieces
+}
+
+/// Finds the conflicts between the given routes, which should be in the order that they were
+/// registered. Like the route tree, static pieces are compared ignoring case when
+/// `case_insensitive` is set.
+pub(crate) fn find_conflicts(
+    routes: &[RegisteredRoute],
+    strict_mode: bool,
+    case_insensitive: bool,
+) -> Vec<RouteConflict> {
+    let mut conflicts = vec![];
+
+    for (i, route) in routes.iter().enumerate() {
+        let (method, path) = (&route.method, &route.path);
+        let path_pieces = pieces(path, strict_mode);
+
+        for other in routes[i + 1..].iter() {
+            let other_path = &other.path;
+            if *method != other.method {
+                continue;
+            }
+
+            let guarded = route.guarded || other.guarded;
+
+            let other_pieces = pieces(other_path, strict_mode);
+            let shared = path_pieces
+                .iter()
+                .zip(other_pieces.iter())
+                .take_while(|(a, b)| a.same_node(b, case_insensitive))
+                .collect::<Vec<_>>();
+            let same_route =
+                shared.len() == path_pieces.len() && shared.len() == other_pieces.len();
+
+            if same_route
+                && shared
+                    .iter()
+                    .all(|(a, b)| a.same_piece(b, case_insensitive))
+            {
+                if guarded {
+                    continue;
+                }
+
+                conflicts.push(RouteConflict::Duplicate {
+                    method: method.to_owned(),
+                    path: path.to_owned(),
+                });
+            } else if shared.iter().any(|(a, b)| a.name_conflicts(b)) {
+                conflicts.push(RouteConflict::ParamName {
+                    method: method.to_owned(),
+                    path: path.to_owned(),
+                    other_path: other_path.to_owned(),
+                });
+            } else if same_route && !guarded {
+                conflicts.push(RouteConflict::Shadowed {
+                    method: method.to_owned(),
+                    path: path.to_owned(),
+                    by: other_path.to_owned(),
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn routes(routes: &[(&str, &str)]) -> Vec<RegisteredRoute> {
+        routes
+            .iter()
+            .map(|(method, path)| RegisteredRoute {
+                method: method.to_string(),
+                path: path.to_string(),
+                guarded: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_should_find_duplicates_param_names_and_shadowed_routes() {
+        let conflicts = find_conflicts(
+            &routes(&[
+                ("GET", "/a"),
+                ("GET", "/a/"),
+                ("POST", "/a"),
+                ("GET", "/b/:id"),
+                ("GET", "/b/:name/c"),
+                ("GET", "/d/:id"),
+                ("GET", "/d/*"),
+                ("GET", "/e/:id<u64>"),
+                ("GET", "/e/:id"),
+                ("GET", "/f/*rest"),
+                ("GET", "/f/*path"),
+            ]),
+            false,
+            false,
+        );
+
+        assert_eq!(
+            conflicts,
+            vec![
+                RouteConflict::Duplicate {
+                    method: "GET".to_owned(),
+                    path: "/a".to_owned(),
+                },
+                RouteConflict::ParamName {
+                    method: "GET".to_owned(),
+                    path: "/b/:id".to_owned(),
+                    other_path: "/b/:name/c".to_owned(),
+                },
+                RouteConflict::Shadowed {
+                    method: "GET".to_owned(),
+                    path: "/d/:id".to_owned(),
+                    by: "/d/*".to_owned(),
+                },
+                RouteConflict::ParamName {
+                    method: "GET".to_owned(),
+                    path: "/f/*rest".to_owned(),
+                    other_path: "/f/*path".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_respect_strict_mode_when_finding_duplicates() {
+        assert!(find_conflicts(&routes(&[("GET", "/a"), ("GET", "/a/")]), true, false).is_empty());
+        assert_eq!(
+            find_conflicts(&routes(&[("GET", "/"), ("GET", "")]), true, false).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn it_should_only_check_param_names_of_guarded_routes() {
+        let mut routes = routes(&[
+            ("GET", "/a"),
+            ("GET", "/a"),
+            ("GET", "/b/:id"),
+            ("GET", "/b/*"),
+            ("GET", "/c/:id"),
+            ("GET", "/c/:name"),
+        ]);
+        for route in routes.iter_mut().step_by(2) {
+            route.guarded = true;
+        }
+
+        assert_eq!(
+            find_conflicts(&routes, false, false),
+            vec![RouteConflict::ParamName {
+                method: "GET".to_owned(),
+                path: "/c/:id".to_owned(),
+                other_path: "/c/:name".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn it_should_compare_static_pieces_ignoring_case_when_case_insensitive() {
+        let routes = routes(&[("GET", "/Users/:id"), ("GET", "/users/:id")]);
+
+        assert!(find_conflicts(&routes, false, false).is_empty());
+        assert_eq!(
+            find_conflicts(&routes, false, true),
+            vec![RouteConflict::Duplicate {
+                method: "GET".to_owned(),
+                path: "/Users/:id".to_owned(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::app::named_routes::join_paths;
+use crate::parser::middleware_traits::MiddlewareTuple;
+
+/// A group of routes under a common prefix, built via `App::scope`. Middleware added to a scope
+/// only runs for the routes declared in it, regardless of the order they were added in, rather
+/// than for everything under the prefix like `App::middleware`.
+pub struct Scope<T> {
+    middleware: Option<MiddlewareTuple<T>>,
+    routes: Vec<ScopedRoute<T>>,
+}
+
+pub(crate) struct ScopedRoute<T> {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) middleware: MiddlewareTuple<T>,
+    pub(crate) isolated: bool,
+    /// The name given to the route via `route_named`, for `App::url_for`.
+    pub(crate) name: Option<String>,
+}
+
+impl<T: Send> Scope<T> {
+    pub(crate) fn new() -> Self {
+        Scope {
+            middleware: None,
+            routes: vec![],
+        }
+    }
+
+    /// Add middleware that runs before the handlers of every route in this scope.
+    pub fn middleware(mut self, middlewares: MiddlewareTuple<T>) -> Self {
+        self.middleware = Some(match self.middleware.take() {
+            Some(middleware) => middleware.combine(middlewares),
+            None => middlewares,
+        });
+
+        self
+    }
+
+    /// Add a route to this scope that responds to the given HTTP method at a given path, relative
+    /// to the scope's prefix.
+    pub fn route(self, method: &str, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.add_route(method, path, middlewares, false, None)
+    }
+
+    /// Add a route to this scope that doesn't inherit any middleware added to the app via
+    /// `App::middleware`. It still runs the middleware of this scope, and any scopes it's in.
+    pub fn route_isolated(self, method: &str, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.add_route(method, path, middlewares, true, None)
+    }
+
+    /// Add a route to this scope that responds to the given HTTP method at a given path, and give
+    /// it a name that can later be used to build urls for it via `App::url_for`. The url includes
+    /// the prefixes of the scope, and of any scopes it's in.
+    pub fn route_named(
+        self,
+        name: &str,
+        method: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<T>,
+    ) -> Self {
+        self.add_route(method, path, middlewares, false, Some(name))
+    }
+
+    /// Add a route to this scope that responds to `GET`s to a given path
+    pub fn get(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("GET", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `HEAD`s to a given path
+    pub fn head(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("HEAD", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `OPTION`s to a given path
+    pub fn options(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("OPTIONS", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `POST`s to a given path
+    pub fn post(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("POST", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `PUT`s to a given path
+    pub fn put(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("PUT", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `DELETE`s to a given path
+    pub fn delete(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("DELETE", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `PATCH`s to a given path
+    pub fn patch(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("PATCH", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `TRACE`s to a given path
+    pub fn trace(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("TRACE", path, middlewares)
+    }
+
+    /// Add a route to this scope that responds to `CONNECT`s to a given path
+    pub fn connect(self, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route("CONNECT", path, middlewares)
+    }
+
+    /// Add a named route to this scope that responds to `GET`s to a given path
+    pub fn get_named(self, name: &str, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route_named(name, "GET", path, middlewares)
+    }
+
+    /// Add a named route to this scope that responds to `POST`s to a given path
+    pub fn post_named(self, name: &str, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route_named(name, "POST", path, middlewares)
+    }
+
+    /// Add a named route to this scope that responds to `PUT`s to a given path
+    pub fn put_named(self, name: &str, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route_named(name, "PUT", path, middlewares)
+    }
+
+    /// Add a named route to this scope that responds to `DELETE`s to a given path
+    pub fn delete_named(self, name: &str, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route_named(name, "DELETE", path, middlewares)
+    }
+
+    /// Add a named route to this scope that responds to `PATCH`s to a given path
+    pub fn patch_named(self, name: &str, path: &str, middlewares: MiddlewareTuple<T>) -> Self {
+        self.route_named(name, "PATCH", path, middlewares)
+    }
+
+    /// Add a nested scope under the given prefix, relative to this scope's prefix. Its routes run
+    /// the middleware of this scope followed by its own.
+    pub fn scope(mut self, prefix: &str, scope: impl FnOnce(Scope<T>) -> Scope<T>) -> Self
+    where
+        T: Clone,
+    {
+        for route in scope(Scope::new()).into_routes() {
+            self.routes.push(ScopedRoute {
+                path: join_paths(prefix, &route.path),
+                ..route
+            });
+        }
+
+        self
+    }
+
+    fn add_route(
+        mut self,
+        method: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<T>,
+        isolated: bool,
+        name: Option<&str>,
+    ) -> Self {
+        self.routes.push(ScopedRoute {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            middleware: middlewares,
+            isolated,
+            name: name.map(str::to_owned),
+        });
+
+        self
+    }
+
+    /// Consumes the scope, giving its routes with the scope's middleware prepended.
+    pub(crate) fn into_routes(self) -> Vec<ScopedRoute<T>>
+    where
+        T: Clone,
+    {
+        let middleware = self.middleware;
+
+        self.routes
+            .into_iter()
+            .map(|route| ScopedRoute {
+                middleware: match middleware.clone() {
+                    Some(middleware) => middleware.combine(route.middleware),
+                    None => route.middleware,
+                },
+                ..route
+            })
+            .collect()
+    }
+}
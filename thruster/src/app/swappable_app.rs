@@ -0,0 +1,92 @@
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+
+use crate::app::App;
+use crate::core::context::Context;
+use crate::core::request::ThrusterRequest;
+
+/// A handle to a committed `App` that can be swapped out for another while a server is running,
+/// e.g. to reload routes from a config file without a restart. Clones of the handle share the
+/// same app, so one can be given to a server via its `new_swappable` constructor and another
+/// kept around to call `swap` on.
+///
+/// Each request is resolved against whichever app is current when it arrives, and requests that
+/// are already in flight finish on the app they started with.
+pub struct SwappableApp<R: ThrusterRequest, T: 'static + Context + Clone + Send + Sync, S: Send> {
+    current: Arc<ArcSwap<App<R, T, S>>>,
+}
+
+impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static + Send>
+    SwappableApp<R, T, S>
+{
+    /// Commits the given app and makes it the current one.
+    pub fn new(app: App<R, T, S>) -> Self {
+        SwappableApp {
+            current: Arc::new(ArcSwap::from_pointee(app.commit())),
+        }
+    }
+
+    /// Commits the given app and atomically replaces the current one with it, returning the
+    /// app that was replaced.
+    pub fn swap(&self, app: App<R, T, S>) -> Arc<App<R, T, S>> {
+        self.current.swap(Arc::new(app.commit()))
+    }
+
+    /// Gets the current app.
+    pub fn load(&self) -> Arc<App<R, T, S>> {
+        self.current.load_full()
+    }
+}
+
+impl<R: ThrusterRequest, T: 'static + Context + Clone + Send + Sync, S: Send> Clone
+    for SwappableApp<R, T, S>
+{
+    fn clone(&self) -> Self {
+        SwappableApp {
+            current: self.current.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::basic_context::BasicContext;
+    use crate::core::errors::ThrusterError;
+    use crate::core::request::Request;
+    use crate::parser::middleware_traits::{MiddlewareTuple, NextFn};
+    use crate::pinbox;
+    use crate::ReusableBoxFuture;
+
+    async fn ok(
+        context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        Ok(context)
+    }
+
+    #[test]
+    fn it_should_keep_the_old_app_for_existing_loads_after_a_swap() {
+        let app = SwappableApp::new(
+            App::<Request, BasicContext, ()>::new_basic()
+                .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, ok))),
+        );
+        let handle = app.clone();
+        let old = app.load();
+
+        handle.swap(
+            App::<Request, BasicContext, ()>::new_basic()
+                .get("/b", MiddlewareTuple::A(pinbox!(BasicContext, ok))),
+        );
+        let new = app.load();
+
+        let matches = |app: &App<Request, BasicContext, ()>, path: &str| {
+            app.resolve_from_method_and_path("GET", path.to_owned())
+                .was_terminal_leaf
+        };
+        assert!(matches(&old, "/a"));
+        assert!(!matches(&old, "/b"));
+        assert!(!matches(&new, "/a"));
+        assert!(matches(&new, "/b"));
+    }
+}
@@ -32,7 +32,7 @@ pub async fn request<T: Context<Response = Response> + Clone + Send + Sync, S: '
     bytes.put(body.as_bytes());
 
     let request = decode(&mut bytes).unwrap().unwrap();
-    let matched_route = app.resolve_from_method_and_path("GET", route.to_owned());
+    let matched_route = app.resolve_request(&request);
     let response = app.resolve(request, matched_route).await.unwrap();
 
     TestResponse::new(response)
@@ -51,7 +51,7 @@ pub async fn get<T: Context<Response = Response> + Clone + Send + Sync, S: 'stat
     bytes.put(body.as_bytes());
 
     let request = decode(&mut bytes).unwrap().unwrap();
-    let matched_route = app.resolve_from_method_and_path("GET", route.to_owned());
+    let matched_route = app.resolve_request(&request);
     let response = app.resolve(request, matched_route).await.unwrap();
 
     TestResponse::new(response)
@@ -70,7 +70,7 @@ pub async fn delete<T: Context<Response = Response> + Clone + Send + Sync, S: 's
     bytes.put(body.as_bytes());
 
     let request = decode(&mut bytes).unwrap().unwrap();
-    let matched_route = app.resolve_from_method_and_path("DELETE", route.to_owned());
+    let matched_route = app.resolve_request(&request);
     let response = app.resolve(request, matched_route).await.unwrap();
 
     TestResponse::new(response)
@@ -92,7 +92,7 @@ pub async fn post<T: Context<Response = Response> + Clone + Send + Sync, S: 'sta
     bytes.put(body.as_bytes());
 
     let request = decode(&mut bytes).unwrap().unwrap();
-    let matched_route = app.resolve_from_method_and_path("POST", route.to_owned());
+    let matched_route = app.resolve_request(&request);
     let response = app.resolve(request, matched_route).await.unwrap();
 
     TestResponse::new(response)
@@ -114,7 +114,7 @@ pub async fn put<T: Context<Response = Response> + Clone + Send + Sync, S: 'stat
     bytes.put(body.as_bytes());
 
     let request = decode(&mut bytes).unwrap().unwrap();
-    let matched_route = app.resolve_from_method_and_path("PUT", route.to_owned());
+    let matched_route = app.resolve_request(&request);
     let response = app.resolve(request, matched_route).await.unwrap();
 
     TestResponse::new(response)
@@ -136,7 +136,7 @@ pub async fn patch<T: Context<Response = Response> + Clone + Send + Sync, S: 'st
     bytes.put(body.as_bytes());
 
     let request = decode(&mut bytes).unwrap().unwrap();
-    let matched_route = app.resolve_from_method_and_path("PATCH", route.to_owned());
+    let matched_route = app.resolve_request(&request);
     let response = app.resolve(request, matched_route).await.unwrap();
 
     TestResponse::new(response)
@@ -1,11 +1,23 @@
 use crate::{RequestWithParams, ReusableBoxFuture};
+use fnv::FnvHashMap;
 use futures::FutureExt;
+use log::warn;
 
 use std::io;
 
+use crate::app::guards::Guard;
+use crate::app::named_routes::join_paths;
+use crate::app::route_conflicts::{find_conflicts, RegisteredRoute};
+use crate::app::{NamedRoutes, PathNormalization, RouteConflict, Scope};
+
 use crate::core::context::Context;
+use crate::core::errors::{ServerError, ThrusterError};
 use crate::core::request::Request;
-use crate::parser::{middleware_traits::MiddlewareTuple, tree::Node, tree::NodeOutput};
+use crate::parser::{
+    middleware_traits::{MiddlewareTuple, NextFn},
+    tree::NodeOutput,
+    tree::{validate_path, Node},
+};
 use crate::{
     context::basic_context::{generate_context, BasicContext},
     core::request::ThrusterRequest,
@@ -25,6 +37,48 @@ pub trait Middleware<CIn: Send, COut: Send, CErr: Send>:
 // type ReturnValue<T> = Result<T, ThrusterError<T>>;
 pub type ReturnValue<T> = T;
 
+/// The handler for automatic `OPTIONS` responses. The `Allow` header is set by the `App` prior to
+/// this running.
+fn auto_options<T: 'static + Context + Send>(
+    mut context: T,
+    _next: NextFn<T>,
+) -> ReusableBoxFuture<Result<T, ThrusterError<T>>> {
+    context.status(204);
+
+    ReusableBoxFuture::new(async move { Ok(context) })
+}
+
+/// How an `App` treats a trailing `/` on request paths, set via `App::set_trailing_slash`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// `/a` and `/a/` are the same route. This is the default.
+    Ignore,
+    /// `/a` and `/a/` are distinct routes, the same as `set_strict_mode(true)`.
+    Strict,
+    /// `/a` and `/a/` are distinct routes, and a request for one that only matches the other is
+    /// redirected to it with the given status, e.g. `301`, or `308` to keep the method and body.
+    Redirect(u16),
+}
+
+/// A route registered on an `App`, as listed by `App::routes`.
+#[derive(Clone, Debug)]
+pub struct RouteInfo {
+    /// The HTTP method the route responds to.
+    pub method: String,
+    /// The full path pattern of the route, including any prefixes added via `router`.
+    pub path: String,
+    /// The names of the params and catch-alls captured by the route, in order.
+    pub params: Vec<String>,
+    /// The number of middleware functions that run for the route, including method-agnostic
+    /// middleware once the app has been committed.
+    pub middleware_count: usize,
+    /// The name given to the route at registration, e.g. via `get_named`.
+    pub name: Option<String>,
+    /// Whether the route was added via `route_with_guard`, so only handles the requests that
+    /// pass its guard.
+    pub guarded: bool,
+}
+
 /// App, the main component of Thruster. The App is the entry point for your application
 /// and handles all incomming requests. Apps are also composeable, that is, via the `subapp`
 /// method, you can use all of the methods and middlewares contained within an app as a subset
@@ -63,12 +117,71 @@ pub type ReturnValue<T> = T;
 /// useful if trying to integrate with a different type of load balancing system within the threads of the
 /// application.
 pub struct App<R: ThrusterRequest, T: 'static + Context + Clone + Send + Sync, S: Send> {
-    pub delete_root: Node<ReturnValue<T>>,
-    pub get_root: Node<ReturnValue<T>>,
-    pub options_root: Node<ReturnValue<T>>,
-    pub post_root: Node<ReturnValue<T>>,
-    pub put_root: Node<ReturnValue<T>>,
-    pub patch_root: Node<ReturnValue<T>>,
+    /// The route trees for the app, keyed by HTTP method. Methods are matched exactly, so
+    /// custom verbs such as `PROPFIND` should be registered in the casing clients send them.
+    pub(crate) method_roots: FnvHashMap<String, Node<ReturnValue<T>>>,
+    /// The route tree consulted when no method tree has a terminal match for a request. It only
+    /// holds the handlers added via `set404` along with any method-agnostic middleware.
+    pub(crate) not_found_root: Node<ReturnValue<T>>,
+    /// The route tree consulted when a path only matches under other methods. It only holds the
+    /// handlers added via `set405` along with any method-agnostic middleware.
+    pub(crate) method_not_allowed_root: Node<ReturnValue<T>>,
+    /// The handler used for method not allowed responses if `set405` was never called.
+    method_not_allowed: Box<
+        dyn Fn(ReturnValue<T>) -> ReusableBoxFuture<Result<ReturnValue<T>, ThrusterError<T>>>
+            + Send
+            + Sync,
+    >,
+    /// The routes added via `route_with_guard`, which are checked in the order they were added
+    /// before the method trees.
+    guarded_routes: Vec<GuardedRoute<R, ReturnValue<T>>>,
+    /// The handler used when a path only matches guarded routes whose guards all reject the
+    /// request.
+    not_acceptable: Box<
+        dyn Fn(ReturnValue<T>) -> ReusableBoxFuture<Result<ReturnValue<T>, ThrusterError<T>>>
+            + Send
+            + Sync,
+    >,
+    /// The handler used to redirect requests to the other form of their path, if enabled via
+    /// `set_trailing_slash`.
+    trailing_slash_redirect: Option<
+        Box<
+            dyn Fn(ReturnValue<T>) -> ReusableBoxFuture<Result<ReturnValue<T>, ThrusterError<T>>>
+                + Send
+                + Sync,
+        >,
+    >,
+    /// The handler used when a request's path is rejected by the path normalization.
+    bad_request: Box<
+        dyn Fn(ReturnValue<T>) -> ReusableBoxFuture<Result<ReturnValue<T>, ThrusterError<T>>>
+            + Send
+            + Sync,
+    >,
+    /// Method-agnostic middleware, kept so that it can be applied to method trees that are
+    /// created after `middleware` was called.
+    method_agnostic_middleware: Vec<(String, MiddlewareTuple<ReturnValue<T>>)>,
+    /// The route tree used to answer `OPTIONS` requests when `set_auto_options` is enabled.
+    pub(crate) auto_options_root: Node<ReturnValue<T>>,
+    auto_options: bool,
+    auto_head: bool,
+    strict_mode: bool,
+    case_insensitive: bool,
+    path_normalization: PathNormalization,
+    /// Whether `commit` panics, rather than logging a warning, when routes conflict.
+    strict_routes: bool,
+    named_routes: NamedRoutes,
+    /// The method and full path of every route in the order they were registered, kept so that
+    /// conflicts between them can be found on commit.
+    registered_routes: Vec<RegisteredRoute>,
+    /// The routes and middleware that weren't added because their paths are malformed, along
+    /// with why, to be reported on commit.
+    invalid_routes: Vec<String>,
+    /// The apps added via `host`, along with the host patterns they handle, in the order they
+    /// were added.
+    host_apps: Vec<(String, App<R, T, S>)>,
+    /// The services added via `mount_service`, along with the path prefixes they handle, in the
+    /// order they were added.
+    pub(crate) mounted_services: Vec<(String, MountedService<R, T::Response>)>,
     /// Generate context is common to all `App`s. It's the function that's called upon receiving a request
     /// that translates an acutal `Request` struct to your custom Context type. It should be noted that
     /// the context_generator should be as fast as possible as this is called with every request, including
@@ -92,37 +205,136 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
     /// Create a new app with the given context generator. The app does not begin listening until start
     /// is called.
     pub fn create(generate_context: fn(R, &S, &str) -> T, state: S) -> Self {
+        let mut auto_options_root = Node::default();
+        auto_options_root.add_value_at_path("/*", MiddlewareTuple::A(auto_options::<T>));
+
         App {
-            delete_root: Node::default(),
-            get_root: Node::default(),
-            options_root: Node::default(),
-            post_root: Node::default(),
-            put_root: Node::default(),
-            patch_root: Node::default(),
+            method_roots: FnvHashMap::default(),
+            not_found_root: Node::default(),
+            method_not_allowed_root: Node::default(),
+            method_not_allowed: Box::new(|mut context| {
+                ReusableBoxFuture::new(async move {
+                    context.status(405);
+
+                    Err(ThrusterError {
+                        context,
+                        message: "Method not allowed".to_string(),
+                        cause: None,
+                    })
+                })
+            }),
+            guarded_routes: vec![],
+            not_acceptable: Box::new(|mut context| {
+                ReusableBoxFuture::new(async move {
+                    context.status(406);
+
+                    Err(ThrusterError {
+                        context,
+                        message: "Not acceptable".to_string(),
+                        cause: None,
+                    })
+                })
+            }),
+            trailing_slash_redirect: None,
+            bad_request: Box::new(|mut context| {
+                ReusableBoxFuture::new(async move {
+                    context.status(400);
+
+                    Err(ThrusterError {
+                        context,
+                        message: "Bad request".to_string(),
+                        cause: None,
+                    })
+                })
+            }),
+            method_agnostic_middleware: vec![],
+            auto_options_root,
+            auto_options: false,
+            auto_head: false,
+            strict_mode: false,
+            case_insensitive: false,
+            path_normalization: PathNormalization::default(),
+            strict_routes: false,
+            named_routes: NamedRoutes::default(),
+            registered_routes: vec![],
+            invalid_routes: vec![],
+            host_apps: vec![],
+            mounted_services: vec![],
             context_generator: generate_context,
             state: std::sync::Arc::new(state),
             connection_timeout: 3600000,
         }
     }
 
+    /// Gets the route tree for the given method, e.g. `"GET"`, if any routes were added for it.
+    /// This replaces the `get_root`, `post_root`, etc. fields of earlier versions.
+    pub fn method_root(&self, method: &str) -> Option<&Node<ReturnValue<T>>> {
+        self.method_roots.get(method)
+    }
+
+    /// Gets the route tree for the given method, creating it (and applying any method-agnostic
+    /// middleware added so far) if this is the first route for that method.
+    pub fn method_root_mut(&mut self, method: &str) -> &mut Node<ReturnValue<T>> {
+        if !self.method_roots.contains_key(method) {
+            let root = self.new_root();
+            self.method_roots.insert(method.to_owned(), root);
+        }
+
+        self.method_roots.get_mut(method).unwrap()
+    }
+
+    /// Checks that the path is well formed, recording it under the given description otherwise
+    /// so that it's reported on commit rather than panicking as it's added.
+    fn is_valid_path(&mut self, description: &str, path: &str) -> bool {
+        match validate_path(path) {
+            Ok(()) => true,
+            Err(e) => {
+                self.invalid_routes.push(format!("{}: {}", description, e));
+
+                false
+            }
+        }
+    }
+
+    /// Creates an empty route tree with the method-agnostic middleware added so far.
+    fn new_root(&self) -> Node<ReturnValue<T>> {
+        let mut root = Node::default();
+        root.strict_mode = self.strict_mode;
+        root.case_insensitive = self.case_insensitive;
+
+        for (path, middlewares) in &self.method_agnostic_middleware {
+            root.add_non_leaf_value_at_path(path, middlewares.clone());
+        }
+
+        root
+    }
+
     /// Add method-agnostic middleware for a route. This is useful for applying headers, logging, and
     /// anything else that might not be sensitive to the HTTP method for the endpoint.
     pub fn middleware(mut self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self
     where
         T: Clone,
     {
-        self.get_root
-            .add_non_leaf_value_at_path(path, middlewares.clone());
-        self.options_root
-            .add_non_leaf_value_at_path(path, middlewares.clone());
-        self.post_root
+        if !self.is_valid_path(path, path) {
+            return self;
+        }
+
+        for root in self.method_roots.values_mut() {
+            root.add_non_leaf_value_at_path(path, middlewares.clone());
+        }
+        for guarded_route in self.guarded_routes.iter_mut() {
+            guarded_route
+                .root
+                .add_non_leaf_value_at_path(path, middlewares.clone());
+        }
+        self.not_found_root
             .add_non_leaf_value_at_path(path, middlewares.clone());
-        self.put_root
+        self.method_not_allowed_root
             .add_non_leaf_value_at_path(path, middlewares.clone());
-        self.delete_root
+        self.auto_options_root
             .add_non_leaf_value_at_path(path, middlewares.clone());
-        self.patch_root
-            .add_non_leaf_value_at_path(path, middlewares);
+        self.method_agnostic_middleware
+            .push((path.to_owned(), middlewares));
 
         self
     }
@@ -141,12 +353,42 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
     /// in to all of the routes. This is a main feature of Thruster, as it allows projects to be extermely
     /// modular and composeable in nature.
     pub fn router(mut self, prefix: &str, app: App<R, T, S>) -> Self {
-        self.get_root.add_node_at_path(prefix, app.get_root);
-        self.options_root.add_node_at_path(prefix, app.options_root);
-        self.post_root.add_node_at_path(prefix, app.post_root);
-        self.put_root.add_node_at_path(prefix, app.put_root);
-        self.delete_root.add_node_at_path(prefix, app.delete_root);
-        self.patch_root.add_node_at_path(prefix, app.patch_root);
+        for (method, root) in app.method_roots {
+            self.method_root_mut(&method).add_node_at_path(prefix, root);
+        }
+        for guarded_route in app.guarded_routes {
+            let mut root = self.new_root();
+            root.add_node_at_path(prefix, guarded_route.root);
+
+            self.guarded_routes.push(GuardedRoute {
+                method: guarded_route.method,
+                guard: guarded_route.guard,
+                root,
+            });
+        }
+        self.not_found_root
+            .add_node_at_path(prefix, app.not_found_root);
+        self.method_not_allowed_root
+            .add_node_at_path(prefix, app.method_not_allowed_root);
+        self.auto_options_root
+            .add_node_at_path(prefix, app.auto_options_root);
+        self.named_routes.add_with_prefix(prefix, app.named_routes);
+        self.registered_routes
+            .extend(
+                app.registered_routes
+                    .into_iter()
+                    .map(|route| RegisteredRoute {
+                        path: join_paths(prefix, &route.path),
+                        ..route
+                    }),
+            );
+        self.invalid_routes.extend(app.invalid_routes);
+        self.mounted_services.extend(
+            app.mounted_services
+                .into_iter()
+                .map(|(path, service)| (join_paths(prefix, &path), service)),
+        );
+        self.apply_case_insensitive();
 
         self
     }
@@ -158,46 +400,291 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
         self.router(prefix, app)
     }
 
-    /// Add a route that responds to `GET`s to a given path
-    pub fn get(mut self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
-        self.get_root.add_value_at_path(path, middlewares);
+    /// Add an app that handles all of the requests sent to the given host, in place of this app's
+    /// routes. Hosts are matched label by label, ignoring case and any port, where a label of
+    /// `:name` captures that label as a param and `*` matches any label, e.g.
+    /// `:tenant.example.com`. Hosts are checked in the order they were added, and requests for
+    /// any other host fall through to this app's routes.
+    pub fn host(mut self, host: &str, app: App<R, T, S>) -> Self {
+        self.host_apps.push((host.to_owned(), app));
 
         self
     }
 
-    /// Add a route that responds to `OPTION`s to a given path
-    pub fn options(mut self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
-        self.options_root.add_value_at_path(path, middlewares);
+    /// Add a route that responds to the given HTTP method at a given path. This is useful for
+    /// methods that don't have a shortcut of their own, e.g. `PROPFIND` or `MKCOL`.
+    pub fn route(
+        mut self,
+        method: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        if !self.is_valid_path(&format!("{} {}", method, path), path) {
+            return self;
+        }
+
+        self.method_root_mut(method)
+            .add_value_at_path(path, middlewares);
+        self.registered_routes.push(RegisteredRoute {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            guarded: false,
+        });
 
         self
     }
 
-    /// Add a route that responds to `POST`s to a given path
-    pub fn post(mut self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
-        self.post_root.add_value_at_path(path, middlewares);
+    /// Add a route that responds to the given HTTP method at a given path, without running any of
+    /// the middleware added via `middleware`, e.g. for a health check that shouldn't need auth.
+    pub fn route_isolated(
+        mut self,
+        method: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        if !self.is_valid_path(&format!("{} {}", method, path), path) {
+            return self;
+        }
+
+        self.method_root_mut(method)
+            .add_isolated_value_at_path(path, middlewares);
+        self.registered_routes.push(RegisteredRoute {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            guarded: false,
+        });
 
         self
     }
 
-    /// Add a route that responds to `PUT`s to a given path
-    pub fn put(mut self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
-        self.put_root.add_value_at_path(path, middlewares);
+    /// Add a group of routes under the given prefix, with middleware that only runs for the routes
+    /// in the group rather than everything under the prefix, e.g.
+    ///
+    /// ```ignore
+    /// app.scope("/admin", |scope| {
+    ///     scope
+    ///         .middleware(m![authenticate])
+    ///         .get("/users", m![list_users])
+    /// })
+    /// ```
+    pub fn scope(
+        mut self,
+        prefix: &str,
+        scope: impl FnOnce(Scope<ReturnValue<T>>) -> Scope<ReturnValue<T>>,
+    ) -> Self
+    where
+        T: Clone,
+    {
+        for route in scope(Scope::new()).into_routes() {
+            let path = join_paths(prefix, &route.path);
+
+            if let Some(name) = &route.name {
+                self.named_routes.add(name, &route.method, &path);
+            }
+
+            self = if route.isolated {
+                self.route_isolated(&route.method, &path, route.middleware)
+            } else {
+                self.route(&route.method, &path, route.middleware)
+            };
+        }
 
         self
     }
 
-    /// Add a route that responds to `DELETE`s to a given path
-    pub fn delete(mut self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
-        self.delete_root.add_value_at_path(path, middlewares);
+    /// Add a route that responds to the given HTTP method at a given path, but only for requests
+    /// that pass the given guard, e.g. one from the `guards` module. Several guarded routes can
+    /// be added for the same path to pick between them by header, content type or query, and
+    /// they're checked in the order they were added before any unguarded route for the path. If
+    /// a path only has guarded routes and none of their guards pass, a `406` is returned.
+    ///
+    /// Guards are only checked when resolving with the whole request, i.e. via
+    /// `resolve_request` or `match_and_resolve`, which the servers do.
+    pub fn route_with_guard(
+        mut self,
+        method: &str,
+        path: &str,
+        guard: impl Fn(&R) -> bool + Send + Sync + 'static,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        if !self.is_valid_path(&format!("{} {}", method, path), path) {
+            return self;
+        }
+
+        let mut root = self.new_root();
+        root.add_value_at_path(path, middlewares);
+        self.registered_routes.push(RegisteredRoute {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            guarded: true,
+        });
+
+        self.guarded_routes.push(GuardedRoute {
+            method: method.to_owned(),
+            guard: Box::new(guard),
+            root,
+        });
 
         self
     }
 
+    /// Add a route that responds to the given HTTP method at a given path, and give it a name that
+    /// can later be used to build urls for it via `url_for`.
+    pub fn route_named(
+        mut self,
+        name: &str,
+        method: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        self.named_routes.add(name, method, path);
+
+        self.route(method, path, middlewares)
+    }
+
+    /// Add a named route that responds to `GET`s to a given path
+    pub fn get_named(
+        self,
+        name: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        self.route_named(name, "GET", path, middlewares)
+    }
+
+    /// Add a named route that responds to `POST`s to a given path
+    pub fn post_named(
+        self,
+        name: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        self.route_named(name, "POST", path, middlewares)
+    }
+
+    /// Add a named route that responds to `PUT`s to a given path
+    pub fn put_named(
+        self,
+        name: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        self.route_named(name, "PUT", path, middlewares)
+    }
+
+    /// Add a named route that responds to `DELETE`s to a given path
+    pub fn delete_named(
+        self,
+        name: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        self.route_named(name, "DELETE", path, middlewares)
+    }
+
+    /// Add a named route that responds to `PATCH`s to a given path
+    pub fn patch_named(
+        self,
+        name: &str,
+        path: &str,
+        middlewares: MiddlewareTuple<ReturnValue<T>>,
+    ) -> Self {
+        self.route_named(name, "PATCH", path, middlewares)
+    }
+
+    /// Builds a url for the route with the given name, filling in its params from the given pairs
+    /// of names and values, e.g. `app.url_for("user_show", &[("id", "42")])`. Routes from apps
+    /// added via `router` include their prefix. Returns `None` if there is no route with that name
+    /// or the url can't be built from the params, e.g. because one is missing, as described on
+    /// `NamedRoutes::url_for`.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        self.named_routes.url_for(name, params)
+    }
+
+    /// Lists the routes of the app, sorted by path and then method. This is useful for printing
+    /// the routes at startup, or for checking in tests that apps added via `router` ended up at
+    /// the expected paths. Guarded routes come before the unguarded route for the same path and
+    /// method, in the order they're checked. Middleware counts are only complete after `commit`.
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let guarded_roots = self
+            .guarded_routes
+            .iter()
+            .map(|guarded_route| (&guarded_route.method, &guarded_route.root, true));
+        let method_roots = self
+            .method_roots
+            .iter()
+            .map(|(method, root)| (method, root, false));
+
+        let mut routes = guarded_roots
+            .chain(method_roots)
+            .flat_map(|(method, root, guarded)| {
+                root.routes().into_iter().map(move |route| RouteInfo {
+                    method: method.to_owned(),
+                    name: self
+                        .named_routes
+                        .name_for(method, &route.path)
+                        .filter(|_| !guarded)
+                        .map(str::to_owned),
+                    path: route.path,
+                    params: route.params,
+                    middleware_count: route.middleware_count,
+                    guarded,
+                })
+            })
+            .collect::<Vec<RouteInfo>>();
+        routes.sort_by(|a, b| (&a.path, &a.method).cmp(&(&b.path, &b.method)));
+
+        routes
+    }
+
+    /// The named routes of the app. Clone these to build urls from within middleware.
+    pub fn named_routes(&self) -> &NamedRoutes {
+        &self.named_routes
+    }
+
+    /// Add a route that responds to `GET`s to a given path
+    pub fn get(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("GET", path, middlewares)
+    }
+
+    /// Add a route that responds to `HEAD`s to a given path
+    pub fn head(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("HEAD", path, middlewares)
+    }
+
+    /// Add a route that responds to `OPTION`s to a given path
+    pub fn options(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("OPTIONS", path, middlewares)
+    }
+
+    /// Add a route that responds to `POST`s to a given path
+    pub fn post(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("POST", path, middlewares)
+    }
+
+    /// Add a route that responds to `PUT`s to a given path
+    pub fn put(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("PUT", path, middlewares)
+    }
+
+    /// Add a route that responds to `DELETE`s to a given path
+    pub fn delete(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("DELETE", path, middlewares)
+    }
+
     /// Add a route that responds to `PATCH`s to a given path
-    pub fn patch(mut self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
-        self.patch_root.add_value_at_path(path, middlewares);
+    pub fn patch(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("PATCH", path, middlewares)
+    }
 
-        self
+    /// Add a route that responds to `TRACE`s to a given path
+    pub fn trace(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("TRACE", path, middlewares)
+    }
+
+    /// Add a route that responds to `CONNECT`s to a given path
+    pub fn connect(self, path: &str, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self {
+        self.route("CONNECT", path, middlewares)
     }
 
     /// Sets the middleware if no route is successfully matched. Note, that due to type restrictions,
@@ -207,42 +694,207 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
     where
         T: Clone,
     {
-        self.get_root.add_value_at_path("/*", middlewares.clone());
-        self.options_root
-            .add_value_at_path("/*", middlewares.clone());
-        self.post_root.add_value_at_path("/*", middlewares.clone());
-        self.put_root.add_value_at_path("/*", middlewares.clone());
-        self.delete_root
-            .add_value_at_path("/*", middlewares.clone());
-        self.patch_root.add_value_at_path("/*", middlewares);
+        self.not_found_root.add_value_at_path("/*", middlewares);
+
+        self
+    }
+
+    /// Sets the middleware if a route is matched, but not for the requested method. By default a
+    /// `405` is returned. Either way, the `Allow` header is set on the context to the methods that
+    /// the route does respond to before the middleware runs.
+    pub fn set405(mut self, middlewares: MiddlewareTuple<ReturnValue<T>>) -> Self
+    where
+        T: Clone,
+    {
+        self.method_not_allowed_root
+            .add_value_at_path("/*", middlewares);
+
+        self
+    }
+
+    /// Sets whether `OPTIONS` requests without a matching `options` route are answered
+    /// automatically. The response is a `204` with the `Allow` header set to the methods the path
+    /// responds to, and it still runs through any method-agnostic middleware, e.g. for CORS.
+    pub fn set_auto_options(mut self, auto_options: bool) -> Self {
+        self.auto_options = auto_options;
+
+        self
+    }
+
+    /// Sets whether `HEAD` requests without a matching `head` route run the `GET` route for the
+    /// path instead. The servers leave the body off of the response while keeping its
+    /// `Content-Length`.
+    pub fn set_auto_head(mut self, auto_head: bool) -> Self {
+        self.auto_head = auto_head;
 
         self
     }
 
     /// Sets whether this app router uses strict mode for route parsing or not. Strict mode considers
     /// `/a` to be distinct from `/a/`.
-    pub fn set_strict_mode(mut self, strict_mode: bool) -> Self
+    pub fn set_strict_mode(self, strict_mode: bool) -> Self
+    where
+        T: Clone,
+    {
+        self.set_trailing_slash(if strict_mode {
+            TrailingSlash::Strict
+        } else {
+            TrailingSlash::Ignore
+        })
+    }
+
+    /// Sets how a trailing `/` on request paths is treated, see `TrailingSlash`. Like
+    /// `set_strict_mode`, this should be called before any routes are added.
+    pub fn set_trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self
     where
         T: Clone,
     {
-        self.get_root.strict_mode = strict_mode;
-        self.options_root.strict_mode = strict_mode;
-        self.post_root.strict_mode = strict_mode;
-        self.put_root.strict_mode = strict_mode;
-        self.delete_root.strict_mode = strict_mode;
-        self.patch_root.strict_mode = strict_mode;
+        let strict_mode = trailing_slash != TrailingSlash::Ignore;
+
+        self.trailing_slash_redirect = match trailing_slash {
+            TrailingSlash::Redirect(status) => Some(Box::new(move |mut context: T| {
+                let location = toggle_trailing_slash(context.route());
+                context.status(status);
+                context.set("Location", &location);
+
+                ReusableBoxFuture::new(async move { Ok(context) })
+            })),
+            _ => None,
+        };
+        self.strict_mode = strict_mode;
+
+        for root in self.method_roots.values_mut() {
+            root.strict_mode = strict_mode;
+        }
+        self.not_found_root.strict_mode = strict_mode;
+        self.method_not_allowed_root.strict_mode = strict_mode;
+        self.auto_options_root.strict_mode = strict_mode;
+        for guarded_route in self.guarded_routes.iter_mut() {
+            guarded_route.root.strict_mode = strict_mode;
+        }
 
         self
     }
 
-    /// Commits and locks in the route tree for usage.
+    /// Sets whether the static pieces of routes are matched ignoring ASCII case, so that
+    /// `/users/:name` matches `/USERS/Jane`. Params are still captured with the casing they were
+    /// sent with. This applies to the routes added so far as well as later ones. Apps added via
+    /// `router` use the setting of the app they're added to, while apps added via `host` use
+    /// their own.
+    pub fn set_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self.apply_case_insensitive();
+
+        self
+    }
+
+    /// Applies the app's case sensitivity to every node of its route trees, including those that
+    /// were added before it was set or were merged in via `router`.
+    fn apply_case_insensitive(&mut self) {
+        let case_insensitive = self.case_insensitive;
+
+        for root in self.method_roots.values_mut() {
+            root.set_case_insensitive(case_insensitive);
+        }
+        self.not_found_root.set_case_insensitive(case_insensitive);
+        self.method_not_allowed_root
+            .set_case_insensitive(case_insensitive);
+        self.auto_options_root
+            .set_case_insensitive(case_insensitive);
+        for guarded_route in self.guarded_routes.iter_mut() {
+            guarded_route.root.set_case_insensitive(case_insensitive);
+        }
+    }
+
+    /// Sets how request paths are normalized before they're matched, e.g. merging slashes or
+    /// percent-decoding params. This applies to every server backend, since they all resolve
+    /// routes through the app. Apps added via `router` use the normalization of the app they're
+    /// added to, while apps added via `host` use their own.
+    pub fn set_path_normalization(mut self, path_normalization: PathNormalization) -> Self {
+        self.path_normalization = path_normalization;
+
+        self
+    }
+
+    /// Sets whether `commit` panics when it finds conflicting routes, such as the same route
+    /// being registered twice or a `router` clobbering a route of the parent app. Otherwise the
+    /// conflicts are logged as warnings.
+    pub fn set_strict_routes(mut self, strict_routes: bool) -> Self {
+        self.strict_routes = strict_routes;
+
+        self
+    }
+
+    /// Finds the routes that conflict with each other: duplicates, params with different names
+    /// at the same level, and routes shadowed by wildcards.
+    pub fn route_conflicts(&self) -> Vec<RouteConflict> {
+        find_conflicts(
+            &self.registered_routes,
+            self.strict_mode,
+            self.case_insensitive,
+        )
+    }
+
+    /// Checks that none of the app's routes, or those of the apps added via `host`, are
+    /// malformed, and that none conflict if `set_strict_routes` is enabled.
+    pub fn validate(&self) -> Result<(), ServerError> {
+        if !self.invalid_routes.is_empty() {
+            return Err(ServerError::InvalidRoutes(self.invalid_routes.clone()));
+        }
+
+        let conflicts = self.route_conflicts();
+        if self.strict_routes && !conflicts.is_empty() {
+            return Err(ServerError::ConflictingRoutes(conflicts));
+        }
+
+        for (_, app) in self.host_apps.iter() {
+            app.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Commits the route tree like `commit`, but returns an error rather than panicking if
+    /// `validate` fails.
+    pub fn try_commit(self) -> Result<Self, ServerError> {
+        self.validate()?;
+
+        Ok(self.commit())
+    }
+
+    /// Commits and locks in the route tree for usage. Conflicting routes are logged as warnings,
+    /// while malformed routes, or conflicting ones if `set_strict_routes` is enabled, cause a
+    /// panic. See `try_commit` for a version that returns them as an error instead.
     pub fn commit(mut self) -> Self {
-        self.get_root = self.get_root.commit();
-        self.options_root = self.options_root.commit();
-        self.post_root = self.post_root.commit();
-        self.put_root = self.put_root.commit();
-        self.delete_root = self.delete_root.commit();
-        self.patch_root = self.patch_root.commit();
+        if let Err(e) = self.validate() {
+            panic!("{}", e);
+        }
+
+        for conflict in self.route_conflicts() {
+            warn!("Conflicting routes: {}", conflict);
+        }
+
+        self.method_roots = self
+            .method_roots
+            .into_iter()
+            .map(|(method, root)| (method, root.commit()))
+            .collect();
+        self.not_found_root = self.not_found_root.commit();
+        self.method_not_allowed_root = self.method_not_allowed_root.commit();
+        self.auto_options_root = self.auto_options_root.commit();
+        self.guarded_routes = self
+            .guarded_routes
+            .into_iter()
+            .map(|guarded_route| GuardedRoute {
+                root: guarded_route.root.commit(),
+                ..guarded_route
+            })
+            .collect();
+        self.host_apps = self
+            .host_apps
+            .into_iter()
+            .map(|(host, app)| (host, app.commit()))
+            .collect();
 
         self
     }
@@ -252,15 +904,238 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
         method: &str,
         path: String,
     ) -> NodeOutput<'m, ReturnValue<T>> {
-        match method {
-            "OPTIONS" => self.options_root.get_value_at_path(path),
-            "POST" => self.post_root.get_value_at_path(path),
-            "PUT" => self.put_root.get_value_at_path(path),
-            "DELETE" => self.delete_root.get_value_at_path(path),
-            "PATCH" => self.patch_root.get_value_at_path(path),
-            // default get
-            _ => self.get_root.get_value_at_path(path),
+        match self.path_normalization.normalize(path.clone()) {
+            Some(path) => self.decoded(self.resolve_normalized_path(method, path)),
+            None => self.bad_request(path),
+        }
+    }
+
+    fn resolve_normalized_path<'m>(
+        &'m self,
+        method: &str,
+        path: String,
+    ) -> NodeOutput<'m, ReturnValue<T>> {
+        if let Some(node) = self.terminal_match(method, &path) {
+            return node;
+        }
+
+        if self.auto_head && method == "HEAD" {
+            if let Some(node) = self.terminal_match("GET", &path) {
+                return node;
+            }
+        }
+
+        if let Some(trailing_slash_redirect) = &self.trailing_slash_redirect {
+            let matches_other_form = |method: &str| {
+                self.method_roots
+                    .get(method)
+                    .map(|root| root.get_value_at_path(path.clone()).trailing_slash_match)
+                    .unwrap_or(false)
+            };
+
+            if matches_other_form(method)
+                || (self.auto_head && method == "HEAD" && matches_other_form("GET"))
+            {
+                let mut node = self.not_found_root.get_value_at_path(path);
+                node.value = trailing_slash_redirect;
+
+                return node;
+            }
+        }
+
+        let allowed_methods = self.allowed_methods_for_path(&path);
+        if !allowed_methods.is_empty() {
+            if self.auto_options && method == "OPTIONS" {
+                let mut node = self.auto_options_root.get_value_at_path(path);
+                node.allowed_methods = allowed_methods;
+
+                return node;
+            }
+
+            let mut node = self.method_not_allowed_root.get_value_at_path(path);
+
+            if !node.was_terminal_leaf {
+                node.value = &self.method_not_allowed;
+            }
+            node.allowed_methods = allowed_methods;
+
+            return node;
+        }
+
+        self.not_found_root.get_value_at_path(path)
+    }
+
+    /// Resolves the route for a request sent to the given host, using the first app added via
+    /// `host` that matches it, or this app's routes otherwise. Any labels captured from the host
+    /// are added to the params.
+    pub fn resolve_from_host_method_and_path<'m>(
+        &'m self,
+        host: Option<&str>,
+        method: &str,
+        path: String,
+    ) -> NodeOutput<'m, ReturnValue<T>> {
+        if let Some(host) = host {
+            for (pattern, app) in self.host_apps.iter() {
+                if let Some(captures) = match_host(pattern, host) {
+                    let mut node = app.resolve_from_host_method_and_path(Some(host), method, path);
+                    for (param, value) in captures {
+                        node.params.add(param, value);
+                    }
+
+                    return node;
+                }
+            }
+        }
+
+        self.resolve_from_method_and_path(method, path)
+    }
+
+    /// Resolves the route for a request, taking into account its host and the guards of any
+    /// routes added via `route_with_guard`, along with its method and path.
+    pub fn resolve_request<'m>(&'m self, request: &R) -> NodeOutput<'m, ReturnValue<T>> {
+        let method = request.method();
+
+        if let Some(host) = request.host() {
+            for (pattern, app) in self.host_apps.iter() {
+                if let Some(captures) = match_host(pattern, host) {
+                    let mut node = app.resolve_request(request);
+                    for (param, value) in captures {
+                        node.params.add(param, value);
+                    }
+
+                    return node;
+                }
+            }
+        }
+
+        let path = match self.path_normalization.normalize(request.path()) {
+            Some(path) => path,
+            None => return self.bad_request(request.path()),
+        };
+
+        let mut rejected = false;
+        for guarded_route in self.guarded_routes.iter() {
+            if guarded_route.method != method {
+                continue;
+            }
+
+            let node = guarded_route.root.get_value_at_path(path.clone());
+            if node.was_terminal_leaf {
+                if (guarded_route.guard)(request) {
+                    return self.decoded(node);
+                }
+
+                rejected = true;
+            }
+        }
+
+        if rejected && self.terminal_match(method, &path).is_none() {
+            let mut node = self.not_found_root.get_value_at_path(path);
+            node.value = &self.not_acceptable;
+
+            return node;
+        }
+
+        self.decoded(self.resolve_normalized_path(method, path))
+    }
+
+    /// Percent-decodes the params of a matched route if the path normalization calls for it.
+    fn decoded<'m>(
+        &self,
+        mut node: NodeOutput<'m, ReturnValue<T>>,
+    ) -> NodeOutput<'m, ReturnValue<T>> {
+        self.path_normalization.decode_params(&mut node.params);
+
+        node
+    }
+
+    /// The response for a path that was rejected by the path normalization.
+    fn bad_request(&self, path: String) -> NodeOutput<'_, ReturnValue<T>> {
+        let mut node = self.not_found_root.get_value_at_path(path);
+        node.value = &self.bad_request;
+
+        node
+    }
+
+    fn terminal_match<'m>(&'m self, method: &str, path: &str) -> Option<NodeOutput<'m, T>> {
+        self.method_roots
+            .get(method)
+            .map(|root| root.get_value_at_path(path.to_owned()))
+            .filter(|node| node.was_terminal_leaf)
+    }
+
+    /// Returns the methods, sorted, that have a route matching the given path. This includes the
+    /// automatic `HEAD` and `OPTIONS` methods if they're enabled.
+    pub fn allowed_methods_for_path(&self, path: &str) -> Vec<String> {
+        let mut allowed_methods = self
+            .method_roots
+            .iter()
+            .filter(|(_, root)| root.get_value_at_path(path.to_owned()).was_terminal_leaf)
+            .map(|(method, _)| method.to_owned())
+            .collect::<Vec<String>>();
+
+        for guarded_route in self.guarded_routes.iter() {
+            if !allowed_methods.contains(&guarded_route.method)
+                && guarded_route
+                    .root
+                    .get_value_at_path(path.to_owned())
+                    .was_terminal_leaf
+            {
+                allowed_methods.push(guarded_route.method.to_owned());
+            }
         }
+
+        if allowed_methods.is_empty() {
+            return allowed_methods;
+        }
+
+        if self.auto_head
+            && allowed_methods.iter().any(|m| m == "GET")
+            && !allowed_methods.iter().any(|m| m == "HEAD")
+        {
+            allowed_methods.push("HEAD".to_owned());
+        }
+
+        if self.auto_options && !allowed_methods.iter().any(|m| m == "OPTIONS") {
+            allowed_methods.push("OPTIONS".to_owned());
+        }
+
+        allowed_methods.sort();
+
+        allowed_methods
+    }
+
+    /// Finds the mounted service whose prefix the request's path is under, if any, taking the
+    /// apps added via `host` into account.
+    fn mounted_service(&self, request: &R) -> Option<&MountedService<R, T::Response>> {
+        if let Some(host) = request.host() {
+            for (pattern, app) in self.host_apps.iter() {
+                if match_host(pattern, host).is_some() {
+                    return app.mounted_service(request);
+                }
+            }
+        }
+
+        if self.mounted_services.is_empty() {
+            return None;
+        }
+
+        // Prefixes are matched against the same normalized path as routes are, so that requests
+        // can't reach a service, or get around one, by adding slashes or dot segments. Rejected
+        // paths are left to the app to respond to.
+        let path = self.path_normalization.normalize(request.path())?;
+        let path = path.split('?').next().unwrap_or("");
+
+        self.mounted_services
+            .iter()
+            .find(|(prefix, _)| {
+                let prefix = prefix.trim_end_matches('/');
+
+                path.strip_prefix(prefix)
+                    .map(|rest| rest.is_empty() || rest.starts_with('/'))
+                    .unwrap_or(false)
+            })
+            .map(|(_, service)| service)
     }
 
     pub fn match_and_resolve<'m>(
@@ -270,21 +1145,18 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
     where
         R: RequestWithParams,
     {
-        let method = request.method();
-        let path = request.path();
-
-        let node = match method {
-            "OPTIONS" => self.options_root.get_value_at_path(path),
-            "POST" => self.post_root.get_value_at_path(path),
-            "PUT" => self.put_root.get_value_at_path(path),
-            "DELETE" => self.delete_root.get_value_at_path(path),
-            "PATCH" => self.patch_root.get_value_at_path(path),
-            // default get
-            _ => self.get_root.get_value_at_path(path),
-        };
+        if let Some(service) = self.mounted_service(&request) {
+            return service(request);
+        }
+
+        let node = self.resolve_request(&request);
 
         request.set_params(node.params);
-        let context = (self.context_generator)(request, &self.state, &node.path);
+        let mut context = (self.context_generator)(request, &self.state, &node.path);
+
+        if !node.allowed_methods.is_empty() {
+            context.set("Allow", &node.allowed_methods.join(", "));
+        }
 
         ReusableBoxFuture::new((node.value)(context).map(|ctx| {
             let ctx = match ctx {
@@ -332,7 +1204,11 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
         request: R,
         matched_route: NodeOutput<'m, T>,
     ) -> Result<T::Response, io::Error> {
-        let context = (self.context_generator)(request, &self.state, &matched_route.path);
+        let mut context = (self.context_generator)(request, &self.state, &matched_route.path);
+
+        if !matched_route.allowed_methods.is_empty() {
+            context.set("Allow", &matched_route.allowed_methods.join(", "));
+        }
 
         let copy = matched_route.value;
         let ctx = (copy)(context).await;
@@ -348,3 +1224,741 @@ impl<R: 'static + ThrusterRequest, T: Context + Clone + Send + Sync, S: 'static
         Ok(ctx.get_response())
     }
 }
+
+/// Adds a trailing `/` to the path of the given route if it doesn't have one, or removes it if
+/// it does, keeping any query string. Leading slashes are collapsed so that the result can't be
+/// taken as a protocol-relative url, e.g. `//example.com`.
+fn toggle_trailing_slash(route: &str) -> String {
+    let (path, query) = route.split_at(route.find('?').unwrap_or(route.len()));
+    let path = path.trim_start_matches('/');
+
+    match path.strip_suffix('/') {
+        Some(path) => format!("/{}{}", path, query),
+        None if path.is_empty() => format!("/{}", query),
+        None => format!("/{}/{}", path, query),
+    }
+}
+
+/// A service added via `App::mount_service`, which is handed the requests under its prefix in
+/// place of the app's routes.
+pub(crate) type MountedService<R, Res> =
+    Box<dyn Fn(R) -> ReusableBoxFuture<Result<Res, io::Error>> + Send + Sync>;
+
+/// A route added via `App::route_with_guard`, which has a route tree of its own so that it can
+/// share a path with other routes.
+struct GuardedRoute<R, T: Clone + Send> {
+    method: String,
+    guard: Guard<R>,
+    root: Node<T>,
+}
+
+/// Matches a host against a host pattern, returning the labels captured by the pattern's params
+/// if it matches.
+fn match_host<'p, 'h>(pattern: &'p str, host: &'h str) -> Option<Vec<(&'p str, &'h str)>> {
+    // Ports, and the trailing dot of fully qualified names, aren't part of the match
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !name.ends_with(':') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            name
+        }
+        _ => host,
+    };
+    let host = host.trim_end_matches('.');
+
+    let pattern_labels = pattern.trim_end_matches('.').split('.');
+    let host_labels = host.split('.');
+    if pattern_labels.clone().count() != host_labels.clone().count() {
+        return None;
+    }
+
+    let mut captures = vec![];
+    for (pattern_label, host_label) in pattern_labels.zip(host_labels) {
+        if let Some(param) = pattern_label.strip_prefix(':') {
+            captures.push((param, host_label));
+        } else if pattern_label != "*" && !pattern_label.eq_ignore_ascii_case(host_label) {
+            return None;
+        }
+    }
+
+    Some(captures)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::app::{guards, DotSegments};
+    use crate::core::request::decode;
+    use crate::core::response::{Response, StatusMessage};
+    use crate::pinbox;
+
+    async fn get_body(
+        mut context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        context.body("get");
+        Ok(context)
+    }
+
+    async fn head_body(
+        mut context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        context.body("head");
+        Ok(context)
+    }
+
+    async fn propfind_body(
+        mut context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        context.body("propfind");
+        Ok(context)
+    }
+
+    async fn not_found_body(
+        mut context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        context.body("not found");
+        context.status(404);
+        Ok(context)
+    }
+
+    async fn header_middleware(
+        context: BasicContext,
+        next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        let mut context = next(context).await?;
+        context.set("X-Middleware", "true");
+        Ok(context)
+    }
+
+    fn run(
+        app: &App<Request, BasicContext, ()>,
+        method: &str,
+        path: &str,
+    ) -> (u32, String, Response) {
+        run_with_headers(app, method, path, &[("Host", "localhost")])
+    }
+
+    fn run_on_host(
+        app: &App<Request, BasicContext, ()>,
+        host: &str,
+        method: &str,
+        path: &str,
+    ) -> (u32, String, Response) {
+        run_with_headers(app, method, path, &[("Host", host)])
+    }
+
+    fn run_with_headers(
+        app: &App<Request, BasicContext, ()>,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> (u32, String, Response) {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+        let mut bytes =
+            BytesMut::from(format!("{} {} HTTP/1.1\r\n{}\r\n", method, path, headers).as_str());
+        let request = decode(&mut bytes).unwrap().unwrap();
+
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let matched = app.resolve_request(&request);
+            let response = app.resolve(request, matched).await.unwrap();
+            let status = match response.status_message {
+                StatusMessage::Ok => 200,
+                StatusMessage::Custom(code, _) => code,
+            };
+
+            (
+                status,
+                String::from_utf8(response.response.clone()).unwrap(),
+                response,
+            )
+        })
+    }
+
+    #[test]
+    fn it_should_route_head_and_custom_methods() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .head("/a", MiddlewareTuple::A(pinbox!(BasicContext, head_body)))
+            .route(
+                "PROPFIND",
+                "/a",
+                MiddlewareTuple::A(pinbox!(BasicContext, propfind_body)),
+            )
+            .commit();
+
+        assert_eq!(run(&app, "GET", "/a").1, "get");
+        assert_eq!(run(&app, "HEAD", "/a").1, "head");
+        assert_eq!(run(&app, "PROPFIND", "/a").1, "propfind");
+    }
+
+    #[test]
+    fn it_should_not_alias_unknown_methods_to_get() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .set404(MiddlewareTuple::A(pinbox!(BasicContext, not_found_body)))
+            .commit();
+
+        let (status, body, _) = run(&app, "TRACE", "/a");
+        assert_eq!(status, 405);
+        assert_ne!(body, "get");
+
+        let (status, body, _) = run(&app, "TRACE", "/b");
+        assert_eq!(status, 404);
+        assert_eq!(body, "not found");
+    }
+
+    #[test]
+    fn it_should_apply_middleware_to_methods_added_later() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .middleware(
+                "/",
+                MiddlewareTuple::A(pinbox!(BasicContext, header_middleware)),
+            )
+            .route(
+                "PROPFIND",
+                "/a",
+                MiddlewareTuple::A(pinbox!(BasicContext, propfind_body)),
+            )
+            .commit();
+
+        let (_, body, response) = run(&app, "PROPFIND", "/a");
+
+        assert_eq!(body, "propfind");
+        assert!(String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("X-Middleware: true"));
+    }
+
+    #[test]
+    fn it_should_respond_405_with_allow_when_only_other_methods_match() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .route(
+                "PROPFIND",
+                "/a",
+                MiddlewareTuple::A(pinbox!(BasicContext, propfind_body)),
+            )
+            .commit();
+
+        let (status, _, response) = run(&app, "POST", "/a");
+
+        assert_eq!(status, 405);
+        assert!(String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("Allow: GET, PROPFIND"));
+        assert_eq!(run(&app, "POST", "/b").0, 404);
+    }
+
+    #[test]
+    fn it_should_use_the_custom_405_handler() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .set405(MiddlewareTuple::A(pinbox!(BasicContext, head_body)))
+            .commit();
+
+        let (_, body, response) = run(&app, "DELETE", "/a");
+
+        assert_eq!(body, "head");
+        assert!(String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("Allow: GET"));
+    }
+
+    #[test]
+    fn it_should_answer_options_and_head_automatically_when_enabled() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .route(
+                "PROPFIND",
+                "/a",
+                MiddlewareTuple::A(pinbox!(BasicContext, propfind_body)),
+            )
+            .set_auto_options(true)
+            .set_auto_head(true)
+            .commit();
+
+        let (status, _, response) = run(&app, "OPTIONS", "/a");
+        assert_eq!(status, 204);
+        assert!(String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("Allow: GET, HEAD, OPTIONS, PROPFIND"));
+
+        let (status, body, _) = run(&app, "HEAD", "/a");
+        assert_eq!(status, 200);
+        assert_eq!(body, "get");
+
+        assert_eq!(run(&app, "OPTIONS", "/b").0, 404);
+    }
+
+    #[test]
+    fn it_should_not_answer_options_and_head_automatically_by_default() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .commit();
+
+        assert_eq!(run(&app, "OPTIONS", "/a").0, 405);
+        assert_eq!(run(&app, "HEAD", "/a").0, 405);
+    }
+
+    #[test]
+    fn it_should_build_urls_for_named_routes_in_routers() {
+        let users = App::<Request, BasicContext, ()>::new_basic().get_named(
+            "user_show",
+            "/users/:id",
+            MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+        );
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .router("/api", users)
+            .commit();
+
+        let url = app.url_for("user_show", &[("id", "42")]).unwrap();
+
+        assert_eq!(url, "/api/users/42");
+        assert_eq!(run(&app, "GET", &url).1, "get");
+    }
+
+    #[test]
+    fn it_should_list_routes_including_routers() {
+        let users = App::<Request, BasicContext, ()>::new_basic()
+            .get_named(
+                "user_show",
+                "/users/:id",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .middleware(
+                "/",
+                MiddlewareTuple::A(pinbox!(BasicContext, header_middleware)),
+            );
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .head("/", MiddlewareTuple::A(pinbox!(BasicContext, head_body)))
+            .router("/api", users)
+            .commit();
+
+        let routes = app
+            .routes()
+            .into_iter()
+            .map(|r| (r.method, r.path, r.params, r.middleware_count, r.name))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            routes,
+            vec![
+                ("GET".to_owned(), "/".to_owned(), vec![], 1, None),
+                ("HEAD".to_owned(), "/".to_owned(), vec![], 1, None),
+                (
+                    "GET".to_owned(),
+                    "/api/users/:id".to_owned(),
+                    vec!["id".to_owned()],
+                    2,
+                    Some("user_show".to_owned())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_find_routes_clobbered_by_routers() {
+        let users = App::<Request, BasicContext, ()>::new_basic()
+            .get("/", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .get("/:id", MiddlewareTuple::A(pinbox!(BasicContext, get_body)));
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get(
+                "/users",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .get(
+                "/users/:name/posts",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .post(
+                "/users",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .router("/users", users);
+
+        assert_eq!(
+            app.route_conflicts(),
+            vec![
+                RouteConflict::Duplicate {
+                    method: "GET".to_owned(),
+                    path: "/users".to_owned(),
+                },
+                RouteConflict::ParamName {
+                    method: "GET".to_owned(),
+                    path: "/users/:name/posts".to_owned(),
+                    other_path: "/users/:id".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_find_routes_differing_only_in_case_when_case_insensitive() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .set_case_insensitive(true)
+            .get(
+                "/Users",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .get(
+                "/users",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            );
+
+        assert_eq!(
+            app.route_conflicts(),
+            vec![RouteConflict::Duplicate {
+                method: "GET".to_owned(),
+                path: "/Users".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "GET /a is registered more than once")]
+    fn it_should_panic_on_conflicting_routes_with_strict_routes() {
+        App::<Request, BasicContext, ()>::new_basic()
+            .set_strict_routes(true)
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .get("/a/", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .commit();
+    }
+
+    #[test]
+    fn it_should_return_malformed_routes_from_try_commit() {
+        let result = App::<Request, BasicContext, ()>::new_basic()
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .get(
+                "/b/*rest/c",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .get(
+                "/c/:id<[a-z>",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .try_commit();
+
+        match result {
+            Err(ServerError::InvalidRoutes(routes)) => {
+                assert_eq!(routes.len(), 2);
+                assert!(routes[0].starts_with("GET /b/*rest/c: The catch-all '*rest'"));
+                assert!(routes[1].starts_with("GET /c/:id<[a-z>: Invalid route param"));
+            }
+            _ => panic!("expected the malformed routes to be returned"),
+        }
+    }
+
+    #[test]
+    fn it_should_route_by_host() {
+        let admin = App::<Request, BasicContext, ()>::new_basic()
+            .get("/", MiddlewareTuple::A(pinbox!(BasicContext, head_body)));
+        let tenants = App::<Request, BasicContext, ()>::new_basic().get(
+            "/",
+            MiddlewareTuple::A(pinbox!(BasicContext, propfind_body)),
+        );
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .host("admin.example.com", admin)
+            .host(":tenant.example.com", tenants)
+            .commit();
+
+        assert_eq!(
+            run_on_host(&app, "Admin.Example.com:8080", "GET", "/").1,
+            "head"
+        );
+        assert_eq!(
+            run_on_host(&app, "acme.example.com", "GET", "/").1,
+            "propfind"
+        );
+        assert_eq!(run_on_host(&app, "example.com", "GET", "/").1, "get");
+        assert_eq!(run_on_host(&app, "a.b.example.com", "GET", "/").1, "get");
+
+        let node =
+            app.resolve_from_host_method_and_path(Some("acme.example.com."), "GET", "/".to_owned());
+        assert_eq!(node.params.get("tenant").unwrap().param, "acme");
+    }
+
+    #[test]
+    fn it_should_pick_routes_by_guard() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .route_with_guard(
+                "GET",
+                "/users",
+                guards::accepts("application/vnd.example.v2+json"),
+                MiddlewareTuple::A(pinbox!(BasicContext, head_body)),
+            )
+            .get(
+                "/users",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .route_with_guard(
+                "POST",
+                "/users",
+                guards::content_type("application/json"),
+                MiddlewareTuple::A(pinbox!(BasicContext, propfind_body)),
+            )
+            .commit();
+
+        let v2 = [
+            ("Host", "localhost"),
+            (
+                "Accept",
+                "text/html, application/vnd.example.v2+json; q=0.9",
+            ),
+        ];
+        assert_eq!(run_with_headers(&app, "GET", "/users", &v2).1, "head");
+        assert_eq!(run(&app, "GET", "/users").1, "get");
+
+        let json = [("Content-Type", "application/json; charset=utf-8")];
+        assert_eq!(
+            run_with_headers(&app, "POST", "/users", &json).1,
+            "propfind"
+        );
+        assert_eq!(run(&app, "POST", "/users").0, 406);
+        assert_eq!(run(&app, "PUT", "/users").0, 405);
+    }
+
+    #[test]
+    fn it_should_list_and_check_guarded_routes() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get(
+                "/users/:id",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .route_with_guard(
+                "GET",
+                "/users/:id",
+                guards::accepts("application/vnd.example.v2+json"),
+                MiddlewareTuple::A(pinbox!(BasicContext, head_body)),
+            )
+            .route_with_guard(
+                "GET",
+                "/users/:name/posts",
+                guards::accepts("application/vnd.example.v2+json"),
+                MiddlewareTuple::A(pinbox!(BasicContext, head_body)),
+            );
+
+        let routes = app
+            .routes()
+            .into_iter()
+            .map(|r| (r.method, r.path, r.guarded))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            routes,
+            vec![
+                ("GET".to_owned(), "/users/:id".to_owned(), true),
+                ("GET".to_owned(), "/users/:id".to_owned(), false),
+                ("GET".to_owned(), "/users/:name/posts".to_owned(), true),
+            ]
+        );
+
+        let conflict = RouteConflict::ParamName {
+            method: "GET".to_owned(),
+            path: "/users/:id".to_owned(),
+            other_path: "/users/:name/posts".to_owned(),
+        };
+        assert_eq!(app.route_conflicts(), vec![conflict.clone(), conflict]);
+    }
+
+    #[test]
+    fn it_should_match_routes_added_before_setting_case_insensitive() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get(
+                "/users/:name",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .get(
+                "/files/*path",
+                MiddlewareTuple::A(pinbox!(BasicContext, head_body)),
+            )
+            .set_case_insensitive(true)
+            .commit();
+
+        assert_eq!(run(&app, "GET", "/USERS/Jane").1, "get");
+        assert_eq!(run(&app, "GET", "/Files/a/b").1, "head");
+
+        let node = app.resolve_from_method_and_path("GET", "/Users/Jane".to_owned());
+        assert_eq!(node.params.get("name").unwrap().param, "Jane");
+    }
+
+    #[test]
+    fn it_should_use_the_case_sensitivity_of_the_app_routers_are_added_to() {
+        let users = App::<Request, BasicContext, ()>::new_basic().get(
+            "/users/:name",
+            MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+        );
+        let insensitive = App::<Request, BasicContext, ()>::new_basic()
+            .set_case_insensitive(true)
+            .router("/api", users)
+            .commit();
+
+        assert_eq!(run(&insensitive, "GET", "/API/Users/Jane").1, "get");
+
+        let users = App::<Request, BasicContext, ()>::new_basic()
+            .set_case_insensitive(true)
+            .get(
+                "/users/:name",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            );
+        let sensitive = App::<Request, BasicContext, ()>::new_basic()
+            .router("/api", users)
+            .commit();
+
+        assert_eq!(run(&sensitive, "GET", "/api/users/Jane").1, "get");
+        assert_eq!(run(&sensitive, "GET", "/api/Users/Jane").0, 404);
+    }
+
+    #[test]
+    fn it_should_normalize_paths_before_matching() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get(
+                "/users/:name",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .set_path_normalization(PathNormalization {
+                decode_params: true,
+                merge_slashes: true,
+                dot_segments: DotSegments::Reject,
+            })
+            .commit();
+
+        assert_eq!(run(&app, "GET", "//users//ann").1, "get");
+        assert_eq!(run(&app, "GET", "/users/../users/ann").0, 400);
+
+        let node = app.resolve_from_method_and_path("GET", "/users/j%C3%B6rg".to_owned());
+        assert_eq!(node.params.get("name").unwrap().param, "jörg");
+    }
+
+    #[test]
+    fn it_should_redirect_to_the_other_trailing_slash_form() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .set_trailing_slash(TrailingSlash::Redirect(308))
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .get("/b/", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .commit();
+
+        let (status, _, response) = run(&app, "GET", "/a/?c=d");
+        assert_eq!(status, 308);
+        assert!(String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("Location: /a?c=d\r\n"));
+
+        let (status, _, response) = run(&app, "GET", "/b");
+        assert_eq!(status, 308);
+        assert!(String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("Location: /b/\r\n"));
+
+        assert_eq!(run(&app, "GET", "/a").1, "get");
+        assert_eq!(run(&app, "GET", "/b/").1, "get");
+        assert_eq!(run(&app, "GET", "/c/").0, 404);
+        assert_eq!(run(&app, "POST", "/a/").0, 404);
+    }
+
+    #[test]
+    fn it_should_not_redirect_to_protocol_relative_urls() {
+        assert_eq!(toggle_trailing_slash("//example.com"), "/example.com/");
+        assert_eq!(toggle_trailing_slash("/"), "/");
+    }
+
+    #[test]
+    fn it_should_only_apply_scope_middleware_to_routes_in_the_scope() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get(
+                "/admin/public",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .scope("/admin", |scope| {
+                scope
+                    .get(
+                        "/users",
+                        MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+                    )
+                    .middleware(MiddlewareTuple::A(pinbox!(BasicContext, header_middleware)))
+                    .scope("/reports", |scope| {
+                        scope.get("/", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+                    })
+            })
+            .commit();
+
+        let has_middleware = |path: &str| {
+            let (status, body, response) = run(&app, "GET", path);
+
+            assert_eq!((status, body.as_str()), (200, "get"));
+            String::from_utf8(response.header_raw.to_vec())
+                .unwrap()
+                .contains("X-Middleware: true")
+        };
+        assert!(has_middleware("/admin/users"));
+        assert!(has_middleware("/admin/reports"));
+        assert!(!has_middleware("/admin/public"));
+    }
+
+    #[test]
+    fn it_should_name_routes_and_use_any_method_in_scopes() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .scope("/admin", |scope| {
+                scope
+                    .head(
+                        "/users",
+                        MiddlewareTuple::A(pinbox!(BasicContext, head_body)),
+                    )
+                    .route(
+                        "PROPFIND",
+                        "/users",
+                        MiddlewareTuple::A(pinbox!(BasicContext, propfind_body)),
+                    )
+                    .scope("/users", |scope| {
+                        scope.get_named(
+                            "admin_user",
+                            "/:id",
+                            MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+                        )
+                    })
+            })
+            .commit();
+
+        assert_eq!(run(&app, "HEAD", "/admin/users").1, "head");
+        assert_eq!(run(&app, "PROPFIND", "/admin/users").1, "propfind");
+        assert_eq!(run(&app, "GET", "/admin/users/1").1, "get");
+        assert_eq!(
+            app.url_for("admin_user", &[("id", "1")]),
+            Some("/admin/users/1".to_owned())
+        );
+    }
+
+    #[test]
+    fn it_should_not_apply_app_middleware_to_isolated_routes() {
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .middleware(
+                "/",
+                MiddlewareTuple::A(pinbox!(BasicContext, header_middleware)),
+            )
+            .get("/a", MiddlewareTuple::A(pinbox!(BasicContext, get_body)))
+            .route_isolated(
+                "GET",
+                "/health",
+                MiddlewareTuple::A(pinbox!(BasicContext, get_body)),
+            )
+            .commit();
+
+        let (_, body, response) = run(&app, "GET", "/health");
+        assert_eq!(body, "get");
+        assert!(!String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("X-Middleware"));
+
+        let (_, _, response) = run(&app, "GET", "/a");
+        assert!(String::from_utf8(response.header_raw.to_vec())
+            .unwrap()
+            .contains("X-Middleware: true"));
+    }
+}
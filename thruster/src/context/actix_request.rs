@@ -41,12 +41,20 @@ impl ActixRequest {
 
 impl ThrusterRequest for ActixRequest {
     fn method(&self) -> &str {
-        ""
+        &self.method
     }
 
     fn path(&self) -> String {
         self.path.to_string()
     }
+
+    fn host(&self) -> Option<&str> {
+        self.header("host")
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
 }
 
 impl RequestWithParams for ActixRequest {
@@ -58,3 +66,157 @@ impl RequestWithParams for ActixRequest {
         &self.params
     }
 }
+
+#[cfg(test)]
+mod test {
+    use actix_web::http::{HeaderName, HeaderValue};
+
+    use crate::app::{guards, App};
+    use crate::context::basic_actix_context::{generate_context, BasicActixContext};
+    use crate::core::errors::ThrusterError;
+    use crate::parser::middleware_traits::{MiddlewareTuple, NextFn};
+    use crate::pinbox;
+    use crate::ReusableBoxFuture;
+
+    use super::*;
+
+    async fn get_body(
+        mut context: BasicActixContext,
+        _next: NextFn<BasicActixContext>,
+    ) -> Result<BasicActixContext, ThrusterError<BasicActixContext>> {
+        context.body("get");
+        Ok(context)
+    }
+
+    async fn post_body(
+        mut context: BasicActixContext,
+        _next: NextFn<BasicActixContext>,
+    ) -> Result<BasicActixContext, ThrusterError<BasicActixContext>> {
+        context.body("post");
+        Ok(context)
+    }
+
+    async fn api_body(
+        mut context: BasicActixContext,
+        _next: NextFn<BasicActixContext>,
+    ) -> Result<BasicActixContext, ThrusterError<BasicActixContext>> {
+        context.body("api");
+        Ok(context)
+    }
+
+    fn request(method: &str, path: &str) -> ActixRequest {
+        ActixRequest {
+            path: path.to_owned(),
+            method: method.to_owned(),
+            headers: HeaderMap::new(),
+            payload: vec![],
+            params: Params::default(),
+            ip: None,
+        }
+    }
+
+    fn with_header(
+        mut request: ActixRequest,
+        name: &'static str,
+        value: &'static str,
+    ) -> ActixRequest {
+        request.headers.insert(
+            HeaderName::from_static(name),
+            HeaderValue::from_static(value),
+        );
+
+        request
+    }
+
+    #[test]
+    fn it_should_route_actix_requests_by_method() {
+        let app = App::<ActixRequest, BasicActixContext, ()>::create(generate_context, ())
+            .get(
+                "/a",
+                MiddlewareTuple::A(pinbox!(BasicActixContext, get_body)),
+            )
+            .post(
+                "/a",
+                MiddlewareTuple::A(pinbox!(BasicActixContext, post_body)),
+            )
+            .commit();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let get = app.match_and_resolve(request("GET", "/a")).await.unwrap();
+                let post = app.match_and_resolve(request("POST", "/a")).await.unwrap();
+
+                assert_eq!(get.response, b"get");
+                assert_eq!(post.response, b"post");
+            });
+    }
+
+    #[test]
+    fn it_should_route_actix_requests_by_host() {
+        let api = App::<ActixRequest, BasicActixContext, ()>::create(generate_context, ()).get(
+            "/a",
+            MiddlewareTuple::A(pinbox!(BasicActixContext, api_body)),
+        );
+        let app = App::<ActixRequest, BasicActixContext, ()>::create(generate_context, ())
+            .get(
+                "/a",
+                MiddlewareTuple::A(pinbox!(BasicActixContext, get_body)),
+            )
+            .host("api.example.com", api)
+            .commit();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let api = app
+                    .match_and_resolve(with_header(
+                        request("GET", "/a"),
+                        "host",
+                        "api.example.com:8080",
+                    ))
+                    .await
+                    .unwrap();
+                let other = app
+                    .match_and_resolve(with_header(request("GET", "/a"), "host", "www.example.com"))
+                    .await
+                    .unwrap();
+
+                assert_eq!(api.response, b"api");
+                assert_eq!(other.response, b"get");
+            });
+    }
+
+    #[test]
+    fn it_should_check_guards_against_actix_request_headers() {
+        let app = App::<ActixRequest, BasicActixContext, ()>::create(generate_context, ())
+            .route_with_guard(
+                "POST",
+                "/a",
+                guards::content_type("application/json"),
+                MiddlewareTuple::A(pinbox!(BasicActixContext, api_body)),
+            )
+            .commit();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let json = app
+                    .match_and_resolve(with_header(
+                        request("POST", "/a"),
+                        "content-type",
+                        "application/json; charset=utf-8",
+                    ))
+                    .await
+                    .unwrap();
+
+                assert_eq!(json.response, b"api");
+                assert_eq!(request("GET", "/a").header("Content-Type"), None);
+                assert_eq!(
+                    with_header(request("GET", "/a"), "content-type", "text/plain")
+                        .header("Content-Type"),
+                    Some("text/plain")
+                );
+            });
+    }
+}
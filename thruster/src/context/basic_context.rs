@@ -1,4 +1,5 @@
 use bytes::Bytes;
+use futures::Stream;
 use serde::Serialize;
 use serde_json::to_vec;
 use std::collections::HashMap;
@@ -78,6 +79,14 @@ impl BasicContext {
         self
     }
 
+    ///
+    /// Stream the body as it's produced, with chunked transfer encoding, rather than setting it
+    /// all at once. Only the homegrown server supports streamed bodies.
+    ///
+    pub fn body_stream(&mut self, stream: impl Stream<Item = Bytes> + Send + 'static) {
+        self.response.body_stream(stream);
+    }
+
     pub fn body_string(&self) -> String {
         str::from_utf8(&self.response.response)
             .unwrap_or("")
@@ -1,5 +1,8 @@
+use crate::app::RouteConflict;
 use crate::core::context::Context;
 use std::error::Error as StdError;
+use std::fmt;
+use std::io;
 
 pub struct ThrusterError<C> {
     pub context: C,
@@ -113,3 +116,167 @@ impl<C: Clone + Default> From<Box<dyn std::error::Error>> for ThrusterError<C> {
         }
     }
 }
+
+/// An error that stops an app from being committed or a server from starting, returned by the
+/// fallible `try_` variants of `App::commit` and the `ThrusterServer` methods in place of a panic.
+#[derive(Debug)]
+pub enum ServerError {
+    /// The host and port didn't resolve to an address.
+    InvalidAddress {
+        address: String,
+        source: Option<io::Error>,
+    },
+    /// Something else is already listening on the address.
+    AddressInUse { address: String, source: io::Error },
+    /// The address couldn't be bound to or listened on for any other reason, e.g. a lack of
+    /// permission.
+    Bind { address: String, source: io::Error },
+    /// The TLS cert or key is missing or couldn't be loaded.
+    Tls(String),
+    /// Routes whose paths are malformed, e.g. with a catch-all that isn't the last piece of the
+    /// route or a param constraint that isn't a valid regex.
+    InvalidRoutes(Vec<String>),
+    /// Routes that conflict with each other, when `App::set_strict_routes` is enabled.
+    ConflictingRoutes(Vec<RouteConflict>),
+    /// Any other IO error, e.g. from creating the runtime.
+    Io(io::Error),
+}
+
+impl ServerError {
+    /// Creates the error for failing to bind to or listen on the given address, telling apart
+    /// addresses that are already in use.
+    pub(crate) fn bind(address: impl ToString, source: io::Error) -> ServerError {
+        let address = address.to_string();
+
+        match source.kind() {
+            io::ErrorKind::AddrInUse => ServerError::AddressInUse { address, source },
+            _ => ServerError::Bind { address, source },
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn lines(f: &mut fmt::Formatter<'_>, lines: &[impl fmt::Display]) -> fmt::Result {
+            for line in lines {
+                write!(f, "\n{}", line)?;
+            }
+
+            Ok(())
+        }
+
+        match self {
+            ServerError::InvalidAddress {
+                address,
+                source: Some(source),
+            } => write!(f, "Could not resolve the address {}: {}", address, source),
+            ServerError::InvalidAddress {
+                address,
+                source: None,
+            } => write!(f, "Could not resolve the address {}", address),
+            ServerError::AddressInUse { address, .. } => {
+                write!(f, "The address {} is already in use", address)
+            }
+            ServerError::Bind { address, source } => {
+                write!(f, "Could not bind to {}: {}", address, source)
+            }
+            ServerError::Tls(message) => write!(f, "Invalid TLS config: {}", message),
+            ServerError::InvalidRoutes(routes) => {
+                write!(f, "Invalid routes:")?;
+                lines(f, routes)
+            }
+            ServerError::ConflictingRoutes(conflicts) => {
+                write!(f, "Conflicting routes:")?;
+                lines(f, conflicts)
+            }
+            ServerError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl StdError for ServerError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ServerError::InvalidAddress {
+                source: Some(source),
+                ..
+            }
+            | ServerError::AddressInUse { source, .. }
+            | ServerError::Bind { source, .. }
+            | ServerError::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ServerError {
+    fn from(e: io::Error) -> Self {
+        ServerError::Io(e)
+    }
+}
+
+/// A request that the homegrown server won't handle, because it's malformed or over one of the
+/// server's `RequestLimits`. The server responds with the error's status, then closes the
+/// connection.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The request couldn't be parsed, e.g. it has an invalid `Content-Length` or chunk size.
+    BadRequest(String),
+    /// The request's URI is longer than the limit.
+    UriTooLong,
+    /// The request's body is larger than the limit.
+    PayloadTooLarge,
+    /// The request has more headers than the limit, or its head is larger than the limit.
+    HeadersTooLarge,
+    /// Reading the request from the connection failed.
+    Io(io::Error),
+}
+
+impl RequestError {
+    /// The status code and reason phrase to respond with, if a response can still be sent.
+    pub fn status(&self) -> Option<(u32, &'static str)> {
+        match self {
+            RequestError::BadRequest(_) => Some((400, "Bad Request")),
+            RequestError::UriTooLong => Some((414, "URI Too Long")),
+            RequestError::PayloadTooLarge => Some((413, "Payload Too Large")),
+            RequestError::HeadersTooLarge => Some((431, "Request Header Fields Too Large")),
+            RequestError::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::BadRequest(message) => write!(f, "Bad request: {}", message),
+            RequestError::UriTooLong => write!(f, "The request's URI is too long"),
+            RequestError::PayloadTooLarge => write!(f, "The request's body is too large"),
+            RequestError::HeadersTooLarge => write!(f, "The request's headers are too large"),
+            RequestError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl StdError for RequestError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            RequestError::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RequestError {
+    fn from(e: io::Error) -> Self {
+        RequestError::Io(e)
+    }
+}
+
+impl From<RequestError> for io::Error {
+    fn from(e: RequestError) -> Self {
+        match e {
+            RequestError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
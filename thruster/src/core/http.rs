@@ -1,18 +1,65 @@
 use bytes::BytesMut;
 use tokio_util::codec::{Decoder, Encoder};
 
-use crate::core::request::{decode, Request};
-use crate::core::response::{encode, Response};
+use crate::core::errors::RequestError;
+use crate::core::request::{decode_awaiting_continue, DecodeProgress, Request, RequestLimits};
+use crate::core::response::{encode, encode_chunk, BodyChunk, Response};
 use std::io;
 
-pub struct Http;
+/// The codec the homegrown server reads requests and writes responses with.
+#[derive(Default)]
+pub struct Http {
+    limits: RequestLimits,
+    /// How far the request at the start of the read buffer has been decoded.
+    progress: DecodeProgress,
+    /// Whether the request being decoded is waiting on a 100 Continue that hasn't been taken.
+    awaiting_continue: bool,
+    /// Whether the request being decoded has already been sent its 100 Continue.
+    continued: bool,
+}
+
+impl Http {
+    /// Creates a codec that rejects requests over the default limits.
+    pub fn new() -> Self {
+        Http::default()
+    }
+
+    /// Creates a codec that rejects requests over the given limits.
+    pub fn with_limits(limits: RequestLimits) -> Self {
+        Http {
+            limits,
+            ..Http::default()
+        }
+    }
+
+    /// Whether the client is waiting on a `100 Continue` before it sends the body of the request
+    /// being decoded. Only returns true once per request.
+    pub(crate) fn take_continue(&mut self) -> bool {
+        std::mem::take(&mut self.awaiting_continue)
+    }
+}
 
 impl Decoder for Http {
     type Item = Request;
-    type Error = io::Error;
+    type Error = RequestError;
 
-    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<Request>> {
-        decode(buf)
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Request>, RequestError> {
+        let mut awaiting_continue = false;
+        let request = decode_awaiting_continue(
+            buf,
+            &self.limits,
+            &mut self.progress,
+            &mut awaiting_continue,
+        )?;
+
+        if request.is_some() {
+            self.continued = false;
+        } else if awaiting_continue && !self.continued {
+            self.awaiting_continue = true;
+            self.continued = true;
+        }
+
+        Ok(request)
     }
 }
 
@@ -25,3 +72,13 @@ impl Encoder<Response> for Http {
         Ok(())
     }
 }
+
+impl Encoder<BodyChunk> for Http {
+    type Error = io::Error;
+
+    fn encode(&mut self, chunk: BodyChunk, buf: &mut BytesMut) -> io::Result<()> {
+        encode_chunk(&chunk, buf);
+
+        Ok(())
+    }
+}
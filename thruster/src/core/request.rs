@@ -1,7 +1,9 @@
+use crate::core::errors::RequestError;
 use crate::parser::tree::Params;
-use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
 use smallvec::SmallVec;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::{fmt, io, str};
 
 pub trait RequestWithParams {
@@ -12,6 +14,17 @@ pub trait RequestWithParams {
 pub trait ThrusterRequest {
     fn method(&self) -> &str;
     fn path(&self) -> String;
+
+    /// The host the request was sent to, including any port, e.g. from the `Host` header. Used
+    /// for routing to apps added via `App::host`.
+    fn host(&self) -> Option<&str> {
+        None
+    }
+
+    /// The value of the header with the given name, ignoring case. Used by route guards.
+    fn header(&self, _name: &str) -> Option<&str> {
+        None
+    }
 }
 
 ///
@@ -26,6 +39,7 @@ pub struct Request {
     path: Slice,
     version: u8,
     pub headers: SmallVec<[(Slice, Slice); 8]>,
+    trailers: SmallVec<[(Slice, Slice); 2]>,
     data: BytesMut,
     pub params: Option<HashMap<String, String>>,
 }
@@ -37,6 +51,12 @@ impl ThrusterRequest for Request {
     fn path(&self) -> String {
         self.path().to_owned()
     }
+    fn host(&self) -> Option<&str> {
+        self.host()
+    }
+    fn header(&self, name: &str) -> Option<&str> {
+        self.header(name)
+    }
 }
 
 type Slice = (usize, usize);
@@ -52,6 +72,7 @@ impl Request {
             path: (0, 0),
             version: 0,
             headers: SmallVec::new(),
+            trailers: SmallVec::new(),
             data: BytesMut::new(),
             params: None,
         }
@@ -65,24 +86,59 @@ impl Request {
     }
 
     ///
-    /// Get the body as a utf8 encoded string
+    /// Get the body as a utf8 encoded string, or an empty string if it isn't valid utf8
     ///
     pub fn body(&self) -> &str {
-        str::from_utf8(self.slice(&self.body)).unwrap()
+        self.try_body().unwrap_or("")
+    }
+
+    ///
+    /// Get the body as a utf8 encoded string, or the error if it isn't valid utf8
+    ///
+    pub fn try_body(&self) -> Result<&str, str::Utf8Error> {
+        str::from_utf8(self.slice(&self.body))
     }
 
     ///
     /// Get the method as a string
     ///
     pub fn method(&self) -> &str {
-        str::from_utf8(self.slice(&self.method)).unwrap()
+        str::from_utf8(self.slice(&self.method)).unwrap_or("")
     }
 
     ///
     /// Get the path as a string ("/some/path")
     ///
     pub fn path(&self) -> &str {
-        str::from_utf8(self.slice(&self.path)).unwrap()
+        str::from_utf8(self.slice(&self.path)).unwrap_or("")
+    }
+
+    ///
+    /// Get the value of the Host header, if there is one ("example.com:8080")
+    ///
+    pub fn host(&self) -> Option<&str> {
+        self.header("host")
+    }
+
+    ///
+    /// Get the value of the first header with the given name, ignoring case
+    ///
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| self.slice(k).eq_ignore_ascii_case(name.as_bytes()))
+            .and_then(|(_, v)| str::from_utf8(self.slice(v)).ok())
+    }
+
+    ///
+    /// Get the value of the first trailer with the given name, ignoring case. Trailers are only
+    /// sent after the body of requests with `Transfer-Encoding: chunked`.
+    ///
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers
+            .iter()
+            .find(|(k, _)| self.slice(k).eq_ignore_ascii_case(name.as_bytes()))
+            .and_then(|(_, v)| str::from_utf8(self.slice(v)).ok())
     }
 
     ///
@@ -92,6 +148,21 @@ impl Request {
         self.version
     }
 
+    ///
+    /// Whether the connection should be kept open for more requests after this one. HTTP/1.1
+    /// connections are unless the request has `Connection: close`, HTTP/1.0 ones only if it has
+    /// `Connection: keep-alive`.
+    ///
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.header("connection").unwrap_or("");
+
+        if self.version >= 1 {
+            !has_token(connection, "close")
+        } else {
+            has_token(connection, "keep-alive")
+        }
+    }
+
     ///
     /// Get an HashMap of the provided headers. The HashMap is lazily computed, so
     /// avoid this method unless you need to access headers.
@@ -100,13 +171,8 @@ impl Request {
         let mut header_map: HashMap<String, Vec<String>> = HashMap::new();
 
         for slice_pair in self.headers.iter() {
-            let k = str::from_utf8(self.slice(&slice_pair.0))
-                .unwrap()
-                .to_owned()
-                .to_lowercase();
-            let v = str::from_utf8(self.slice(&slice_pair.1))
-                .unwrap()
-                .to_owned();
+            let k = String::from_utf8_lossy(self.slice(&slice_pair.0)).to_lowercase();
+            let v = String::from_utf8_lossy(self.slice(&slice_pair.1)).into_owned();
 
             match header_map.get_mut(&k) {
                 Some(val) => {
@@ -138,7 +204,7 @@ impl Request {
     where
         T: serde::de::DeserializeOwned,
     {
-        serde_json::from_str(self.body())
+        serde_json::from_slice(self.raw_body())
     }
 
     ///
@@ -163,62 +229,610 @@ impl fmt::Debug for Request {
     }
 }
 
+/// Limits on the size of the requests that the homegrown server will decode, set via
+/// `Server::with_request_limits`. Requests over them are answered with a 413, 414 or 431.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestLimits {
+    /// The most headers a request can have. Defaults to 100.
+    pub max_headers: usize,
+    /// The largest a request's head, its request line and headers, can be in bytes. Defaults to
+    /// 64KB.
+    pub max_header_size: usize,
+    /// The longest a request's URI can be in bytes. Defaults to 8KB.
+    pub max_uri_length: usize,
+    /// The largest a request's body can be in bytes, whether it's sent with a `Content-Length`
+    /// or chunked. Defaults to 16MB.
+    pub max_body_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_headers: 100,
+            max_header_size: 64 * 1024,
+            max_uri_length: 8 * 1024,
+            max_body_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// The parsed request line and headers of a request, as slices of the buffer.
+struct Head {
+    method: Slice,
+    path: Slice,
+    version: u8,
+    headers: SmallVec<[(Slice, Slice); 8]>,
+    len: usize,
+    body: BodyLength,
+    expects_continue: bool,
+}
+
+enum BodyLength {
+    Fixed(usize),
+    Chunked,
+}
+
+///
+/// Decode a request from the buffer with the default `RequestLimits`, see `decode_with_limits`.
+///
 pub fn decode(buf: &mut BytesMut) -> io::Result<Option<Request>> {
-    // TODO: we should grow this headers array if parsing fails and asks
-    //       for more headers
-    let (method, path, version, headers, amt, body_len) = {
-        let mut headers = [httparse::EMPTY_HEADER; 32];
-        let mut r = httparse::Request::new(&mut headers);
-        let mut body_len: usize = 0;
-        let mut header_vec = SmallVec::new();
-        let status = r.parse(buf).map_err(|e| {
-            let msg = format!("failed to parse http request: {:?}", e);
-            eprintln!("msg: {}", msg);
-            eprintln!("dump: {:#?}", buf);
-            io::Error::new(io::ErrorKind::Other, msg)
-        })?;
-        let amt = match status {
-            httparse::Status::Complete(amt) => amt,
-            httparse::Status::Partial => return Ok(None),
-        };
-        let toslice = |a: &[u8]| {
-            let start = a.as_ptr() as usize - buf.as_ptr() as usize;
-            assert!(start < buf.len());
-            (start, start + a.len())
-        };
-        for header in r.headers.iter() {
-            let header_name = httplib::header::CONTENT_LENGTH;
+    Ok(decode_with_limits(buf, &RequestLimits::default())?)
+}
+
+///
+/// Decode a request from the buffer once all of it has arrived, leaving anything after it in the
+/// buffer. Returns an error if the request is malformed or over the limits.
+///
+pub fn decode_with_limits(
+    buf: &mut BytesMut,
+    limits: &RequestLimits,
+) -> Result<Option<Request>, RequestError> {
+    decode_awaiting_continue(buf, limits, &mut DecodeProgress::default(), &mut false)
+}
+
+/// How far the request at the start of a buffer has been decoded, kept by the codec between
+/// reads so that a request that trickles in isn't parsed again from the start on every read.
+#[derive(Default)]
+pub(crate) struct DecodeProgress {
+    /// The head of the request, once it has all arrived.
+    head: Option<Head>,
+    /// Where the next chunk size of a chunked body starts, or its trailers once the last chunk
+    /// has been read. Zero until the body has started to be decoded.
+    pos: usize,
+    /// Where the data of each of the chunks read so far is.
+    chunks: SmallVec<[Slice; 4]>,
+    body_len: usize,
+    last_chunk_read: bool,
+}
+
+/// A request's data once all of it has arrived, along with where its body and trailers are.
+struct DecodedBody {
+    data: BytesMut,
+    body: Slice,
+    trailers: SmallVec<[(Slice, Slice); 2]>,
+}
+
+/// Decodes like `decode_with_limits`, picking up from the given progress of an earlier call with
+/// the same buffer. Also sets `awaiting_continue` once the head of a request with
+/// `Expect: 100-continue` has arrived without its body, since the client is waiting to be told
+/// to send it.
+pub(crate) fn decode_awaiting_continue(
+    buf: &mut BytesMut,
+    limits: &RequestLimits,
+    progress: &mut DecodeProgress,
+    awaiting_continue: &mut bool,
+) -> Result<Option<Request>, RequestError> {
+    let request = decode_with_progress(buf, limits, progress);
+
+    match request {
+        Ok(None) => {
+            *awaiting_continue = matches!(&progress.head, Some(head) if head.expects_continue);
+        }
+        // Once a request has been decoded, or turned out to be invalid, the next one starts
+        // afresh
+        _ => {
+            *awaiting_continue = false;
+            *progress = DecodeProgress::default();
+        }
+    }
+
+    request
+}
+
+fn decode_with_progress(
+    buf: &mut BytesMut,
+    limits: &RequestLimits,
+    progress: &mut DecodeProgress,
+) -> Result<Option<Request>, RequestError> {
+    let head = match progress.head.take() {
+        Some(head) => head,
+        None => {
+            let mut headers = [httparse::EMPTY_HEADER; 32];
+            let head = if limits.max_headers <= headers.len() {
+                parse_head(buf, &mut headers[..limits.max_headers], limits)
+            } else {
+                match parse_head(buf, &mut headers, limits) {
+                    // Only requests with lots of headers pay for a larger array
+                    Err(RequestError::HeadersTooLarge) => parse_head(
+                        buf,
+                        &mut vec![httparse::EMPTY_HEADER; limits.max_headers],
+                        limits,
+                    ),
+                    head => head,
+                }
+            };
 
-            if header.name == header_name {
-                let value = str::from_utf8(header.value).unwrap_or("0");
-                body_len = value.parse::<usize>().unwrap_or(0);
+            match head? {
+                Some(head) => head,
+                None => return Ok(None),
             }
+        }
+    };
+
+    let decoded = match head.body {
+        BodyLength::Fixed(body_len) => decode_fixed(buf, head.len, body_len),
+        BodyLength::Chunked => decode_chunked(buf, head.len, limits, progress)?,
+    };
+
+    match decoded {
+        Some(DecodedBody {
+            data,
+            body,
+            trailers,
+        }) => Ok(Some(Request {
+            method: head.method,
+            path: head.path,
+            version: head.version,
+            headers: head.headers,
+            trailers,
+            data,
+            body,
+            params: None,
+        })),
+        None => {
+            progress.head = Some(head);
 
-            header_vec.push((toslice(header.name.as_bytes()), toslice(header.value)));
+            Ok(None)
         }
+    }
+}
 
-        (
-            toslice(r.method.unwrap().as_bytes()),
-            toslice(r.path.unwrap().as_bytes()),
-            r.version.unwrap(),
-            header_vec,
-            amt,
-            body_len,
-        )
+/// Decodes a body of the given length, once all of it is in the buffer.
+fn decode_fixed(buf: &mut BytesMut, head_len: usize, body_len: usize) -> Option<DecodedBody> {
+    // Anything after the body is the start of the next pipelined request, so it's left in the
+    // buffer
+    if buf.len() < head_len + body_len {
+        None
+    } else {
+        Some(DecodedBody {
+            data: buf.split_to(head_len + body_len),
+            body: (head_len, head_len + body_len),
+            trailers: SmallVec::new(),
+        })
+    }
+}
+
+/// Parses the request line and headers, once they've all arrived, into as many headers as
+/// there's room for.
+fn parse_head<'b>(
+    buf: &'b [u8],
+    headers: &mut [httparse::Header<'b>],
+    limits: &RequestLimits,
+) -> Result<Option<Head>, RequestError> {
+    let mut r = httparse::Request::new(headers);
+    let status = r.parse(buf).map_err(|e| match e {
+        httparse::Error::TooManyHeaders => RequestError::HeadersTooLarge,
+        e => RequestError::BadRequest(e.to_string()),
+    })?;
+    let amt = match status {
+        httparse::Status::Complete(amt) => amt,
+        httparse::Status::Partial if buf.len() > limits.max_header_size => {
+            return Err(RequestError::HeadersTooLarge)
+        }
+        httparse::Status::Partial => return Ok(None),
+    };
+
+    if amt > limits.max_header_size {
+        return Err(RequestError::HeadersTooLarge);
+    }
+
+    let toslice = |a: &[u8]| {
+        let start = a.as_ptr() as usize - buf.as_ptr() as usize;
+        assert!(start < buf.len());
+        (start, start + a.len())
+    };
+
+    let path = r.path.unwrap_or_default();
+    if path.len() > limits.max_uri_length {
+        return Err(RequestError::UriTooLong);
+    }
+
+    let mut content_length = None;
+    let mut transfer_encoding = None;
+    let mut expects_continue = false;
+    let mut header_vec = SmallVec::new();
+    for header in r.headers.iter() {
+        if header.name.eq_ignore_ascii_case("content-length") {
+            let length = str::from_utf8(header.value)
+                .ok()
+                .filter(|value| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|value| value.parse::<usize>().ok())
+                .ok_or_else(|| RequestError::BadRequest("invalid Content-Length".to_owned()))?;
+
+            if matches!(content_length.replace(length), Some(l) if l != length) {
+                return Err(RequestError::BadRequest(
+                    "conflicting Content-Lengths".to_owned(),
+                ));
+            }
+        }
+
+        // Repeated headers make up a single list of codings, and the only one supported is a
+        // lone chunked, so a second Transfer-Encoding is never valid.
+        if header.name.eq_ignore_ascii_case("transfer-encoding")
+            && transfer_encoding.replace(header.value).is_some()
+        {
+            return Err(RequestError::BadRequest(
+                "multiple Transfer-Encodings".to_owned(),
+            ));
+        }
+
+        if header.name.eq_ignore_ascii_case("expect") {
+            expects_continue = header.value.eq_ignore_ascii_case(b"100-continue");
+        }
+
+        header_vec.push((toslice(header.name.as_bytes()), toslice(header.value)));
+    }
+
+    // A chunked body takes precedence over any Content-Length. Other codings, e.g. gzip, aren't
+    // supported, so can't be decoded, and without chunked there's no telling where the body ends.
+    let body = match transfer_encoding {
+        Some(value) if is_chunked(value) => BodyLength::Chunked,
+        Some(_) => {
+            return Err(RequestError::BadRequest(
+                "unsupported Transfer-Encoding".to_owned(),
+            ))
+        }
+        None => BodyLength::Fixed(content_length.unwrap_or(0)),
     };
 
-    if amt + body_len != buf.len() {
-        Ok(None)
+    if let BodyLength::Fixed(body_len) = body {
+        if body_len > limits.max_body_size {
+            return Err(RequestError::PayloadTooLarge);
+        }
+    }
+
+    Ok(Some(Head {
+        method: toslice(r.method.unwrap_or_default().as_bytes()),
+        path: toslice(path.as_bytes()),
+        version: r.version.unwrap_or_default(),
+        headers: header_vec,
+        len: amt,
+        body,
+        // HTTP/1.0 clients don't know to wait for a 100 Continue
+        expects_continue: expects_continue && r.version == Some(1),
+    }))
+}
+
+/// Whether a comma separated header value, like that of `Connection`, has the given token.
+pub(crate) fn has_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|candidate| candidate.trim().eq_ignore_ascii_case(token))
+}
+
+/// Whether chunked is the only transfer coding in a `Transfer-Encoding` header's value.
+fn is_chunked(value: &[u8]) -> bool {
+    str::from_utf8(value)
+        .map(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false)
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body once all of its chunks and trailers are in the
+/// buffer, reading as many chunks as have arrived into the progress on each call. The chunks are
+/// joined after the request's head, followed by the trailers, so that the body can be sliced like
+/// any other.
+fn decode_chunked(
+    buf: &mut BytesMut,
+    head_len: usize,
+    limits: &RequestLimits,
+    progress: &mut DecodeProgress,
+) -> Result<Option<DecodedBody>, RequestError> {
+    let invalid = |msg: &str| RequestError::BadRequest(msg.to_owned());
+
+    if progress.pos == 0 {
+        progress.pos = head_len;
+    }
+
+    while !progress.last_chunk_read {
+        let pos = progress.pos;
+        let (size_len, size) = match httparse::parse_chunk_size(&buf[pos..]) {
+            Ok(httparse::Status::Complete(parsed)) => parsed,
+            Ok(httparse::Status::Partial) => return Ok(None),
+            Err(_) => return Err(invalid("invalid chunk size")),
+        };
+
+        if size == 0 {
+            progress.pos = pos + size_len;
+            progress.last_chunk_read = true;
+            break;
+        }
+
+        let size = usize::try_from(size)
+            .ok()
+            .filter(|size| *size <= limits.max_body_size - progress.body_len)
+            .ok_or(RequestError::PayloadTooLarge)?;
+        let start = pos + size_len;
+        let end = start + size;
+
+        if buf.len() < end + 2 {
+            return Ok(None);
+        }
+
+        if &buf[end..end + 2] != b"\r\n" {
+            return Err(invalid("chunk missing its trailing CRLF"));
+        }
+
+        progress.chunks.push((start, end));
+        progress.body_len += size;
+        progress.pos = end + 2;
+    }
+
+    // The trailers section is made up of header lines, ending with an empty line like the head
+    let pos = progress.pos;
+    let mut trailer_headers = [httparse::EMPTY_HEADER; 16];
+    let trailers = if limits.max_headers <= trailer_headers.len() {
+        parse_trailers(
+            &buf[pos..],
+            &mut trailer_headers[..limits.max_headers],
+            limits,
+        )
     } else {
-        Ok(Request {
-            method,
-            path,
-            version,
-            headers,
-            data: buf.split_to(amt + body_len),
-            body: (amt, amt + body_len),
-            params: None,
+        match parse_trailers(&buf[pos..], &mut trailer_headers, limits) {
+            Err(RequestError::HeadersTooLarge) => parse_trailers(
+                &buf[pos..],
+                &mut vec![httparse::EMPTY_HEADER; limits.max_headers],
+                limits,
+            ),
+            trailers => trailers,
+        }
+    };
+    let (trailers_len, trailers) = match trailers? {
+        Some(trailers) => trailers,
+        None => return Ok(None),
+    };
+
+    let body_len = progress.body_len;
+    let mut data = BytesMut::with_capacity(head_len + body_len + trailers_len);
+    data.extend_from_slice(&buf[..head_len]);
+    for (start, end) in progress.chunks.iter() {
+        data.extend_from_slice(&buf[*start..*end]);
+    }
+    let trailers_start = data.len();
+    data.extend_from_slice(&buf[pos..pos + trailers_len]);
+    buf.advance(pos + trailers_len);
+
+    let offset = |(start, end): Slice| (trailers_start + start, trailers_start + end);
+    let trailers = trailers
+        .into_iter()
+        .map(|(name, value)| (offset(name), offset(value)))
+        .collect();
+
+    Ok(Some(DecodedBody {
+        data,
+        body: (head_len, head_len + body_len),
+        trailers,
+    }))
+}
+
+/// Parses the trailers at the start of the buffer, once they've all arrived, into as many
+/// headers as there's room for. Returns the length of the trailers section along with the
+/// trailers, as slices from its start.
+fn parse_trailers<'b>(
+    buf: &'b [u8],
+    headers: &mut [httparse::Header<'b>],
+    limits: &RequestLimits,
+) -> Result<Option<(usize, SmallVec<[(Slice, Slice); 2]>)>, RequestError> {
+    let (len, parsed) = match httparse::parse_headers(buf, headers) {
+        Ok(httparse::Status::Complete((len, _))) if len > limits.max_header_size => {
+            return Err(RequestError::HeadersTooLarge)
         }
-        .into())
+        Ok(httparse::Status::Complete(parsed)) => parsed,
+        Ok(httparse::Status::Partial) if buf.len() > limits.max_header_size => {
+            return Err(RequestError::HeadersTooLarge)
+        }
+        Ok(httparse::Status::Partial) => return Ok(None),
+        Err(httparse::Error::TooManyHeaders) => return Err(RequestError::HeadersTooLarge),
+        Err(_) => return Err(RequestError::BadRequest("invalid trailers".to_owned())),
+    };
+
+    let toslice = |a: &[u8]| {
+        let start = a.as_ptr() as usize - buf.as_ptr() as usize;
+        (start, start + a.len())
+    };
+    let trailers = parsed
+        .iter()
+        .map(|trailer| (toslice(trailer.name.as_bytes()), toslice(trailer.value)))
+        .collect();
+
+    Ok(Some((len, trailers)))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_util::codec::Decoder;
+
+    use super::*;
+    use crate::core::http::Http;
+
+    #[test]
+    fn it_should_decode_chunked_bodies_and_trailers() {
+        let request = b"POST /upload HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nChecksum: abc\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+
+        // Nothing is decoded until the whole body and trailers have arrived
+        for len in [60, 80, request.len() - 20] {
+            let mut buf = BytesMut::from(&request[..len]);
+            assert!(decode(&mut buf).unwrap().is_none());
+        }
+
+        let mut buf = BytesMut::from(&request[..]);
+        let request = decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(request.body(), "hello, world");
+        assert_eq!(request.trailer("checksum"), Some("abc"));
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(&buf[..], b"GET / HTTP/1.1\r\n\r\n");
+    }
+
+    #[test]
+    fn it_should_decode_chunked_bodies_that_trickle_in() {
+        let request = b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n7\r\n, world\r\n0\r\nChecksum: abc\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let mut codec = Http::new();
+        let mut buf = BytesMut::new();
+        let mut decoded = vec![];
+
+        for byte in request.iter() {
+            buf.extend_from_slice(&[*byte]);
+
+            if let Some(request) = codec.decode(&mut buf).unwrap() {
+                decoded.push(request);
+            }
+        }
+
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].body(), "hello, world");
+        assert_eq!(decoded[0].trailer("checksum"), Some("abc"));
+        assert_eq!(decoded[1].path(), "/");
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn it_should_decode_trailers_up_to_the_header_limit() {
+        let limits = RequestLimits {
+            max_headers: 20,
+            ..RequestLimits::default()
+        };
+        let request = |trailers: usize| {
+            format!(
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n{}Last: yes\r\n\r\n",
+                "A: b\r\n".repeat(trailers - 1)
+            )
+        };
+
+        let decoded = decode_with_limits(&mut BytesMut::from(request(20).as_bytes()), &limits)
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded.trailer("last"), Some("yes"));
+
+        let error = decode_with_limits(&mut BytesMut::from(request(21).as_bytes()), &limits)
+            .err()
+            .and_then(|e| e.status())
+            .map(|(code, _)| code);
+        assert_eq!(error, Some(431));
+    }
+
+    #[test]
+    fn it_should_leave_pipelined_requests_in_the_buffer() {
+        let mut buf = BytesMut::from(
+            &b"POST /a HTTP/1.1\r\nContent-Length: 2\r\n\r\nhiGET /b HTTP/1.1\r\n\r\n"[..],
+        );
+
+        let first = decode(&mut buf).unwrap().unwrap();
+        let second = decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(first.body(), "hi");
+        assert_eq!(second.path(), "/b");
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn it_should_reject_malformed_chunks() {
+        let mut buf = BytesMut::from(
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhello\r\n0\r\n\r\n"[..],
+        );
+
+        assert!(decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn it_should_reject_requests_over_the_limits() {
+        let limits = RequestLimits {
+            max_headers: 40,
+            max_header_size: 1024,
+            max_uri_length: 16,
+            max_body_size: 4,
+        };
+        let status = |request: &[u8]| {
+            decode_with_limits(&mut BytesMut::from(request), &limits)
+                .err()
+                .and_then(|e| e.status())
+                .map(|(code, _)| code)
+        };
+        let many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "A: b\r\n".repeat(41));
+
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody"),
+            None
+        );
+        assert_eq!(status(b"GET /a/long/long/path HTTP/1.1\r\n\r\n"), Some(414));
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nContent-Length: 5\r\n\r\n"),
+            Some(413)
+        );
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n"),
+            Some(413)
+        );
+        assert_eq!(status(many_headers.as_bytes()), Some(431));
+        assert_eq!(status(&many_headers.as_bytes()[..30]), None);
+        assert_eq!(
+            status(format!("GET / HTTP/1.1\r\nA: {}", "b".repeat(1024)).as_bytes()),
+            Some(431)
+        );
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"),
+            Some(400)
+        );
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n"),
+            Some(400)
+        );
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            Some(400)
+        );
+        assert_eq!(
+            status(b"GET / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n"),
+            Some(400)
+        );
+        assert_eq!(
+            status(
+                b"GET / HTTP/1.1\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"
+            ),
+            Some(400)
+        );
+        assert_eq!(status(b"GET\0 / HTTP/1.1\r\n\r\n"), Some(400));
+    }
+
+    #[test]
+    fn it_should_grow_the_headers_up_to_the_limit() {
+        let request = format!(
+            "GET / HTTP/1.1\r\n{}Last: yes\r\n\r\n",
+            "A: b\r\n".repeat(50)
+        );
+        let request = decode(&mut BytesMut::from(request.as_bytes()))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(request.header("last"), Some("yes"));
+    }
+
+    #[test]
+    fn it_should_not_panic_on_bodies_that_are_not_utf8() {
+        let mut buf = BytesMut::from(&b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\n\xff\xfe"[..]);
+        let request = decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(request.body(), "");
+        assert!(request.try_body().is_err());
+        assert_eq!(request.raw_body(), b"\xff\xfe");
     }
 }
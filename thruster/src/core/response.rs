@@ -1,11 +1,39 @@
 use std::fmt::{self, Write};
+use std::pin::Pin;
+use std::sync::Mutex;
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
+use futures::Stream;
+
+use crate::core::request::has_token;
+
+/// A response body that's sent as it's produced, rather than all at once.
+pub type BodyStream = Pin<Box<dyn Stream<Item = Bytes> + Send>>;
 
 pub struct Response {
     pub response: Vec<u8>,
     pub status_message: StatusMessage,
     pub header_raw: BytesMut,
+    /// Whether the body is left off the wire when encoding, e.g. for responses to `HEAD`s. The
+    /// `Content-Length` header still reflects the length of the body.
+    pub omit_body: bool,
+    /// Set via `body_stream`. Contexts have to be `Sync`, so the stream is held in a mutex to
+    /// make the response `Sync` without requiring it of the stream, e.g. so that a `BoxStream`
+    /// or a channel's receiver can be used. It's only ever taken out by value, via
+    /// `take_body_stream`, so the mutex is never contended.
+    stream: Option<Mutex<BodyStream>>,
+    streamed: bool,
+    /// The minor version of HTTP/1 to respond with, matching the request's.
+    version: u8,
+}
+
+/// A piece of a streamed body, encoded with chunked transfer encoding after the response's head.
+pub(crate) enum BodyChunk {
+    Data(Bytes),
+    End,
+    /// A piece sent as is, for HTTP/1.0 clients, which don't understand chunks. The body ends
+    /// when the connection is closed.
+    Unframed(Bytes),
 }
 
 pub enum StatusMessage {
@@ -19,10 +47,20 @@ impl Response {
             response: Vec::new(),
             status_message: StatusMessage::Ok,
             header_raw: BytesMut::new(),
+            omit_body: false,
+            stream: None,
+            streamed: false,
+            version: 1,
         }
     }
 
+    /// Sets the status, with the given reason phrase, or the code's canonical one if it's empty,
+    /// e.g. "Not Found" for a 404.
     pub fn status_code(&mut self, code: u32, message: &str) -> &mut Response {
+        let message = match message {
+            "" => reason_phrase(code).unwrap_or(""),
+            message => message,
+        };
         self.status_message = StatusMessage::Custom(code, message.to_string());
         self
     }
@@ -48,28 +86,180 @@ impl Response {
         self.response = b;
         self
     }
+
+    pub fn omit_body(&mut self, omit_body: bool) -> &mut Response {
+        self.omit_body = omit_body;
+        self
+    }
+
+    /// Streams the body with chunked transfer encoding instead of sending `response`, so that
+    /// large or long-lived bodies, like server-sent events, don't have to be held in memory.
+    /// Only the homegrown server supports streamed bodies.
+    pub fn body_stream(
+        &mut self,
+        stream: impl Stream<Item = Bytes> + Send + 'static,
+    ) -> &mut Response {
+        self.stream = Some(Mutex::new(Box::pin(stream)));
+        self.streamed = true;
+        self
+    }
+
+    /// Whether the body is streamed, see `body_stream`.
+    pub fn is_streamed(&self) -> bool {
+        self.streamed
+    }
+
+    /// Whether the status allows a body. Informational (1xx), 204 No Content and 304 Not Modified
+    /// responses never have one, so any body that's set isn't sent.
+    pub fn allows_body(&self) -> bool {
+        let code = self.status_message.code();
+
+        !(100..200).contains(&code) && code != 204 && code != 304
+    }
+
+    /// Sets the minor version of HTTP/1 to respond with, e.g. 0 for HTTP/1.0.
+    pub(crate) fn version(&mut self, version: u8) -> &mut Response {
+        self.version = version;
+        self
+    }
+
+    /// Whether a `Connection: close` header has been set, so the connection is closed after
+    /// the response is sent.
+    pub fn closes_connection(&self) -> bool {
+        self.header_raw[..].split(|b| *b == b'\n').any(|line| {
+            let line = String::from_utf8_lossy(line);
+
+            match line.split_once(':') {
+                Some((name, value)) => {
+                    name.trim().eq_ignore_ascii_case("connection") && has_token(value, "close")
+                }
+                None => false,
+            }
+        })
+    }
+
+    /// Takes the streamed body, if there is one, to send after the response's head. The response
+    /// is still encoded as streamed once it's been taken.
+    pub(crate) fn take_body_stream(&mut self) -> Option<BodyStream> {
+        self.stream
+            .take()
+            .map(|stream| stream.into_inner().unwrap_or_else(|e| e.into_inner()))
+    }
 }
 
 pub fn encode(msg: &Response, buf: &mut BytesMut) {
-    let length = msg.response.len();
     let now = crate::core::date::now();
 
     write!(
         FastWrite(buf),
-        "\
-         HTTP/1.1 {}\r\n\
-         Content-Length: {}\r\n\
-         Date: {}\r\n\
-         ",
-        msg.status_message,
-        length,
-        now
+        "HTTP/1.{} {}\r\n",
+        msg.version,
+        msg.status_message
     )
     .unwrap();
 
+    // Streamed bodies to HTTP/1.0 clients are delimited by closing the connection instead
+    if !msg.allows_body() {
+        // 1xx, 204 and 304 responses never have a body, so there's nothing to frame
+    } else if !msg.is_streamed() {
+        write!(FastWrite(buf), "Content-Length: {}\r\n", msg.response.len()).unwrap();
+    } else if msg.version >= 1 {
+        buf.extend_from_slice(b"Transfer-Encoding: chunked\r\n");
+    }
+
+    write!(FastWrite(buf), "Date: {}\r\n", now).unwrap();
+
     buf.extend_from_slice(&msg.header_raw);
     buf.extend_from_slice(b"\r\n");
-    buf.extend_from_slice(msg.response.as_slice());
+
+    if !msg.omit_body && !msg.is_streamed() && msg.allows_body() {
+        buf.extend_from_slice(msg.response.as_slice());
+    }
+}
+
+/// The canonical reason phrase for a status code, from the IANA HTTP status code registry.
+pub fn reason_phrase(code: u32) -> Option<&'static str> {
+    let phrase = match code {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        102 => "Processing",
+        103 => "Early Hints",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        203 => "Non-Authoritative Information",
+        204 => "No Content",
+        205 => "Reset Content",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        208 => "Already Reported",
+        226 => "IM Used",
+        300 => "Multiple Choices",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        305 => "Use Proxy",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        402 => "Payment Required",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        418 => "I'm a teapot",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Entity",
+        423 => "Locked",
+        424 => "Failed Dependency",
+        425 => "Too Early",
+        426 => "Upgrade Required",
+        428 => "Precondition Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        451 => "Unavailable For Legal Reasons",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        506 => "Variant Also Negotiates",
+        507 => "Insufficient Storage",
+        508 => "Loop Detected",
+        510 => "Not Extended",
+        511 => "Network Authentication Required",
+        _ => return None,
+    };
+
+    Some(phrase)
+}
+
+pub(crate) fn encode_chunk(chunk: &BodyChunk, buf: &mut BytesMut) {
+    match chunk {
+        // An empty chunk would mark the end of the body
+        BodyChunk::Data(data) if data.is_empty() => (),
+        BodyChunk::Data(data) => {
+            write!(FastWrite(buf), "{:X}\r\n", data.len()).unwrap();
+            buf.extend_from_slice(data);
+            buf.extend_from_slice(b"\r\n");
+        }
+        BodyChunk::End => buf.extend_from_slice(b"0\r\n\r\n"),
+        BodyChunk::Unframed(data) => buf.extend_from_slice(data),
+    }
 }
 
 impl Default for Response {
@@ -96,6 +286,16 @@ impl<'a> fmt::Write for FastWrite<'a> {
     }
 }
 
+impl StatusMessage {
+    /// The status code, e.g. 200.
+    pub fn code(&self) -> u32 {
+        match *self {
+            StatusMessage::Ok => 200,
+            StatusMessage::Custom(code, _) => code,
+        }
+    }
+}
+
 impl fmt::Display for StatusMessage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -104,3 +304,83 @@ impl fmt::Display for StatusMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn it_should_keep_the_content_length_when_omitting_the_body() {
+        let mut response = Response::new();
+        response.body("hello").omit_body(true);
+
+        let mut buf = BytesMut::new();
+        encode(&response, &mut buf);
+        let encoded = String::from_utf8(buf.to_vec()).unwrap();
+
+        assert!(encoded.contains("Content-Length: 5\r\n"));
+        assert!(encoded.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn it_should_accept_streams_that_are_not_sync() {
+        let mut response = Response::new();
+        response.body_stream(futures::stream::iter(vec![Bytes::from("hello")]).boxed());
+
+        assert!(response.is_streamed());
+        assert!(response.take_body_stream().is_some());
+    }
+
+    #[test]
+    fn it_should_encode_streamed_bodies_in_chunks() {
+        let mut response = Response::new();
+        response.body_stream(futures::stream::empty());
+
+        let mut buf = BytesMut::new();
+        encode(&response, &mut buf);
+        for chunk in [
+            BodyChunk::Data(Bytes::from("hello, world")),
+            BodyChunk::Data(Bytes::new()),
+            BodyChunk::End,
+        ] {
+            encode_chunk(&chunk, &mut buf);
+        }
+        let encoded = String::from_utf8(buf.to_vec()).unwrap();
+
+        assert!(encoded.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!encoded.contains("Content-Length"));
+        assert!(encoded.ends_with("\r\n\r\nC\r\nhello, world\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn it_should_use_the_canonical_reason_phrase_unless_one_is_given() {
+        let mut response = Response::new();
+
+        response.status_code(404, "");
+        assert_eq!(response.status_message.to_string(), "404 Not Found");
+
+        response.status_code(404, "Nothing Here");
+        assert_eq!(response.status_message.to_string(), "404 Nothing Here");
+
+        response.status_code(599, "");
+        assert_eq!(response.status_message.to_string(), "599 ");
+    }
+
+    #[test]
+    fn it_should_not_send_a_body_for_statuses_without_one() {
+        for code in [101, 204, 304] {
+            let mut response = Response::new();
+            response.body("hello").status_code(code, "");
+
+            let mut buf = BytesMut::new();
+            encode(&response, &mut buf);
+            let encoded = String::from_utf8(buf.to_vec()).unwrap();
+
+            assert!(encoded.starts_with(&format!("HTTP/1.1 {}", code)));
+            assert!(!encoded.contains("Content-Length"));
+            assert!(encoded.ends_with("\r\n\r\n"));
+        }
+    }
+}
@@ -19,14 +19,25 @@ pub mod parser;
 pub use crate::core::context::Context;
 pub use crate::core::context_state::ContextState;
 pub use crate::core::errors;
+pub use crate::core::errors::{RequestError, ServerError};
 pub use crate::core::http::Http;
 pub use crate::core::middleware::MiddlewareResult;
-pub use crate::core::request::{decode, Request, RequestWithParams, ThrusterRequest};
-pub use crate::core::response::{encode, Response};
+pub use crate::core::request::{
+    decode, decode_with_limits, Request, RequestLimits, RequestWithParams, ThrusterRequest,
+};
+pub use crate::core::response::{encode, reason_phrase, Response};
 pub use crate::core::{MiddlewareFn, MiddlewareNext, MiddlewareReturnValue};
+pub use app::guards;
 pub use app::testing_async as testing;
 pub use app::App;
+pub use app::NamedRoutes;
+pub use app::RouteConflict;
+pub use app::RouteInfo;
+pub use app::Scope;
+pub use app::SwappableApp;
 pub use app::Testable;
+pub use app::TrailingSlash;
+pub use app::{DotSegments, PathNormalization};
 
 // Reexport tokio_util::sync::ReusableBoxFuture;
 pub use tokio_util::sync::ReusableBoxFuture;
@@ -100,3 +100,27 @@ macro_rules! expand_combine {
 type M<T> = MiddlewareFnPointer<T>;
 expand_combine!(P, O, N, M, L, K, J, I, H, G, F, E, D, C, B, A);
 generate_tuples!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+impl<T> MiddlewareTuple<T> {
+    /// The number of middleware functions in the tuple.
+    pub fn count(&self) -> usize {
+        match self {
+            MiddlewareTuple::A(..) => 1,
+            MiddlewareTuple::B(..) => 2,
+            MiddlewareTuple::C(..) => 3,
+            MiddlewareTuple::D(..) => 4,
+            MiddlewareTuple::E(..) => 5,
+            MiddlewareTuple::F(..) => 6,
+            MiddlewareTuple::G(..) => 7,
+            MiddlewareTuple::H(..) => 8,
+            MiddlewareTuple::I(..) => 9,
+            MiddlewareTuple::J(..) => 10,
+            MiddlewareTuple::K(..) => 11,
+            MiddlewareTuple::L(..) => 12,
+            MiddlewareTuple::M(..) => 13,
+            MiddlewareTuple::N(..) => 14,
+            MiddlewareTuple::O(..) => 15,
+            MiddlewareTuple::P(..) => 16,
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use crate::ReusableBoxFuture;
 use fnv::FnvHashMap;
+use regex::Regex;
 
-use std::str::Split;
+use std::str::{FromStr, Split};
 use std::{fmt, fmt::Debug};
 
 use crate::core::context::Context;
@@ -11,6 +12,8 @@ use crate::parser::middleware_traits::*;
 const ROOT_ROUTE_ID: &str = "__root__";
 const WILDCARD_ROUTE_ID: char = '*';
 const PARAM_ROUTE_LEADING_CHAR: char = ':';
+const PARAM_CONSTRAINT_LEADING_CHAR: char = '<';
+const PARAM_CONSTRAINT_TRAILING_CHAR: char = '>';
 
 // For potential future use
 // use std::future::Future;
@@ -25,6 +28,10 @@ pub struct Node<T: Clone + Send> {
     /// The wildcard node which will be matched against given no children match this value.
     wildcard_node: Option<Box<Node<T>>>,
 
+    /// The param name and node for a named catch-all, e.g. `*rest`, which matches the remainder of
+    /// the path when nothing more specific does.
+    catch_all: Option<(String, Box<Node<T>>)>,
+
     /// The path piece of the param that this node matches against.
     path_piece: String,
 
@@ -35,6 +42,13 @@ pub struct Node<T: Clone + Send> {
     /// The nodes which are children to this node. Empty if node is a leaf.
     children: Vec<Node<T>>,
 
+    /// The param nodes with constraints, e.g. `:id<u64>`, which are checked in the order they were
+    /// added after the exact children and before the wildcard node.
+    constrained_nodes: Vec<Node<T>>,
+
+    /// The param name and constraint for this node if it's one of its parent's constrained nodes.
+    constrained_param: Option<(String, ParamConstraint)>,
+
     /// The committed middleware, only usable once node has been consumed and replaced.
     committed_middleware:
         Box<dyn Fn(T) -> ReusableBoxFuture<Result<T, ThrusterError<T>>> + Send + Sync>,
@@ -48,6 +62,10 @@ pub struct Node<T: Clone + Send> {
     /// Is the node a leaf node or not. Leaf nodes are nodes in which a route can be terminated.
     is_leaf: bool,
 
+    /// Whether the value of this node is committed on its own, without the non-leaf middleware
+    /// from this node and the nodes above it.
+    isolated: bool,
+
     /// If there is a non-leaf value to this node, then this is the middleware that represents it
     /// and should be pushed down the tree on commit.
     non_leaf_value: Option<MiddlewareTuple<T>>,
@@ -60,6 +78,9 @@ pub struct Node<T: Clone + Send> {
 
     /// Should respect "strict mode", that is disambiguating /a and /a/
     pub(crate) strict_mode: bool,
+
+    /// Whether static path pieces are matched ignoring ASCII case, e.g. /Users matching /users
+    pub(crate) case_insensitive: bool,
 }
 
 impl<T: Clone + Send> Debug for Node<T> {
@@ -71,6 +92,114 @@ impl<T: Clone + Send> Debug for Node<T> {
     }
 }
 
+/// A constraint on the path piece that a param matches, e.g. `u64` in `/users/:id<u64>`.
+/// The names of primitive types constrain the piece to values that parse as that type,
+/// anything else is treated as a regex that must match the whole piece. Note that since
+/// routes are split on `/`, a regex constraint can't match across multiple pieces.
+#[derive(Clone, Debug)]
+pub enum ParamConstraint {
+    Type(String, fn(&str) -> bool),
+    Regex(Regex),
+}
+
+impl ParamConstraint {
+    /// Parses a constraint as it appears in a route, without the surrounding `<>`. Panics if the
+    /// constraint is neither a known type nor a valid regex.
+    pub fn parse(constraint: &str) -> ParamConstraint {
+        ParamConstraint::try_parse(constraint).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Parses a constraint like `parse`, returning an error if the constraint is neither a known
+    /// type nor a valid regex.
+    pub fn try_parse(constraint: &str) -> Result<ParamConstraint, String> {
+        fn parses<P: FromStr>(piece: &str) -> bool {
+            piece.parse::<P>().is_ok()
+        }
+
+        let parser: Option<fn(&str) -> bool> = match constraint {
+            "u8" => Some(parses::<u8>),
+            "u16" => Some(parses::<u16>),
+            "u32" => Some(parses::<u32>),
+            "u64" => Some(parses::<u64>),
+            "u128" => Some(parses::<u128>),
+            "usize" => Some(parses::<usize>),
+            "i8" => Some(parses::<i8>),
+            "i16" => Some(parses::<i16>),
+            "i32" => Some(parses::<i32>),
+            "i64" => Some(parses::<i64>),
+            "i128" => Some(parses::<i128>),
+            "isize" => Some(parses::<isize>),
+            "f32" => Some(parses::<f32>),
+            "f64" => Some(parses::<f64>),
+            "bool" => Some(parses::<bool>),
+            _ => None,
+        };
+
+        match parser {
+            Some(parser) => Ok(ParamConstraint::Type(constraint.to_owned(), parser)),
+            None => Regex::new(&format!("^(?:{})$", constraint))
+                .map(ParamConstraint::Regex)
+                .map_err(|e| format!("Invalid route param constraint '{}': {}", constraint, e)),
+        }
+    }
+
+    /// Whether the given path piece satisfies the constraint.
+    pub fn matches(&self, piece: &str) -> bool {
+        match self {
+            ParamConstraint::Type(_, parser) => parser(piece),
+            ParamConstraint::Regex(regex) => regex.is_match(piece),
+        }
+    }
+}
+
+/// Checks that a route's path can be added to a tree, returning why not rather than panicking
+/// like adding it would.
+pub(crate) fn validate_path(path: &str) -> Result<(), String> {
+    let mut pieces = path.split('/');
+
+    while let Some(piece) = pieces.next() {
+        if piece.starts_with(WILDCARD_ROUTE_ID)
+            && piece.len() > 1
+            && pieces.clone().any(|piece| !piece.is_empty())
+        {
+            return Err(format!(
+                "The catch-all '{}' must be the last piece of the route it's in",
+                piece
+            ));
+        }
+
+        if let Some((_, constraint)) = split_constrained_param(piece) {
+            ParamConstraint::try_parse(constraint)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a static path piece of a route matches the given piece of a path.
+fn pieces_match(case_insensitive: bool, route_piece: &str, piece: &str) -> bool {
+    if case_insensitive {
+        route_piece.eq_ignore_ascii_case(piece)
+    } else {
+        route_piece == piece
+    }
+}
+
+/// Splits a param path piece with a constraint, e.g. `:id<u64>`, into its name and constraint.
+fn split_constrained_param(path_piece: &str) -> Option<(&str, &str)> {
+    let piece = path_piece.strip_prefix(PARAM_ROUTE_LEADING_CHAR)?;
+    let constraint_start = piece.find(PARAM_CONSTRAINT_LEADING_CHAR)?;
+
+    if !piece.ends_with(PARAM_CONSTRAINT_TRAILING_CHAR) {
+        return None;
+    }
+
+    Some((
+        &piece[..constraint_start],
+        &piece[constraint_start + 1..piece.len() - 1],
+    ))
+}
+
 #[derive(Debug, Default)]
 pub struct Params {
     inner: Vec<Param>,
@@ -81,12 +210,22 @@ impl Params {
         self.inner.iter().find(|p| p.key == key)
     }
 
+    /// Gets the param parsed as the given type, e.g. `params.get_as::<u64>("id")`. Returns `None`
+    /// if the param is missing or doesn't parse.
+    pub fn get_as<P: FromStr>(&self, key: &str) -> Option<P> {
+        self.get(key).and_then(|p| p.param.parse::<P>().ok())
+    }
+
     pub fn add(&mut self, key: &str, val: &str) {
         self.inner.push(Param {
             key: key.to_owned(),
             param: val.to_owned(),
         })
     }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Param> {
+        self.inner.iter_mut()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -95,11 +234,29 @@ pub struct Param {
     pub param: String,
 }
 
+/// A route that can be matched in a tree, as listed by `Node::routes`.
+#[derive(Clone, Debug)]
+pub struct RouteEntry {
+    /// The full path pattern of the route, e.g. `/users/:id`.
+    pub path: String,
+    /// The names of the params and catch-alls captured by the route, in order.
+    pub params: Vec<String>,
+    /// The number of middleware functions that run for the route. This only includes middleware
+    /// added via `add_non_leaf_value_at_path` once the tree has been committed.
+    pub middleware_count: usize,
+}
+
 pub struct NodeOutput<'m, T> {
     pub value: &'m Box<dyn Fn(T) -> ReusableBoxFuture<Result<T, ThrusterError<T>>> + Send + Sync>,
     pub params: Params,
     pub path: String,
     pub was_terminal_leaf: bool,
+    /// The methods that do match the path when the requested method did not. Only populated by
+    /// the `App` when it resolves to its method not allowed handler.
+    pub allowed_methods: Vec<String>,
+    /// In strict mode, whether the path would have matched a route if it had, or didn't have, a
+    /// trailing `/`.
+    pub trailing_slash_match: bool,
 }
 
 pub struct OwnedNodeOutput<'m, T> {
@@ -123,9 +280,12 @@ impl<T: 'static + Context + Clone + Send> Default for Node<T> {
         Node {
             value: None,
             wildcard_node: None,
+            catch_all: None,
             path_piece: ROOT_ROUTE_ID.to_string(),
             param_name: None,
             children: vec![],
+            constrained_nodes: vec![],
+            constrained_param: None,
             committed_middleware: Box::new(|mut c| {
                 ReusableBoxFuture::new(async move {
                     c.status(404);
@@ -140,9 +300,11 @@ impl<T: 'static + Context + Clone + Send> Default for Node<T> {
             committed_value: None,
             has_committed_middleware: false,
             is_leaf: false,
+            isolated: false,
             non_leaf_value: None,
             fastmatch_map: FnvHashMap::default(),
             strict_mode: false,
+            case_insensitive: false,
         }
     }
 }
@@ -163,12 +325,20 @@ impl<T: 'static + Context + Clone + Send> Default for Node<T> {
 impl<T: 'static + Context + Clone + Send> Node<T> {
     /// Gets the value at the end of the path.
     pub fn get_value_at_path<'m, 'k: 'm>(&'k self, path: String) -> NodeOutput<'m, T> {
-        if let Some(value) = self.fastmatch_map.get(&path) {
+        let fastmatch_value = if self.case_insensitive {
+            self.fastmatch_map.get(&path.to_ascii_lowercase())
+        } else {
+            self.fastmatch_map.get(&path)
+        };
+
+        if let Some(value) = fastmatch_value {
             return NodeOutput {
                 value,
                 params: Params::default(),
                 path,
                 was_terminal_leaf: true,
+                allowed_methods: vec![],
+                trailing_slash_match: false,
             };
         }
 
@@ -205,6 +375,30 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
         self.add_value_at_split_path(split, value, false)
     }
 
+    /// Adds the value at the given path as a leaf that doesn't inherit any of the non-leaf values
+    /// (i.e. middleware) added at or above the path.
+    pub fn add_isolated_value_at_path(&mut self, path: &str, value: MiddlewareTuple<T>) {
+        self.add_value_at_path(path, value);
+
+        // Find the node the value was added to, which outside of strict mode stops short of any
+        // empty pieces
+        let mut pieces = path
+            .trim_start_matches('/')
+            .split('/')
+            .collect::<Vec<&str>>();
+        if !self.strict_mode {
+            if let Some(i) = pieces.iter().position(|piece| piece.is_empty()) {
+                pieces.truncate(i);
+            }
+        }
+
+        if pieces.is_empty() {
+            self.isolated = true;
+        } else if let Some(node) = self.get_node_at_split_path(pieces.join("/").split('/')) {
+            node.isolated = true;
+        }
+    }
+
     /// Merges this node with another node, attempting to combine values at matching paths.
     pub fn add_node_at_path(&mut self, path: &str, mut added_node: Node<T>) {
         let mut split = path.split('/');
@@ -228,6 +422,7 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                 }
                 None => added_node.value,
             };
+            node.isolated = node.isolated || added_node.isolated;
 
             // Also consider wildcards here
             let wildcard_node = node.wildcard_node.take();
@@ -255,6 +450,7 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                             _ => None,
                         };
                     wildcard_node.value = middleware;
+                    wildcard_node.isolated = wildcard_node.isolated || added_wildcard_node.isolated;
 
                     Some(wildcard_node)
                 }
@@ -263,10 +459,36 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                 _ => None,
             };
 
+            // And named catch-alls, the added one taking precedence for the name
+            node.catch_all = match (node.catch_all.take(), added_node.catch_all.take()) {
+                (Some((_, mut catch_all_node)), Some((name, mut added_catch_all_node))) => {
+                    catch_all_node.value = match (
+                        catch_all_node.value.take(),
+                        added_catch_all_node.value.take(),
+                    ) {
+                        (Some(value), Some(added_value)) => Some(value.combine(added_value)),
+                        (value, added_value) => value.or(added_value),
+                    };
+                    catch_all_node.isolated =
+                        catch_all_node.isolated || added_catch_all_node.isolated;
+
+                    Some((name, catch_all_node))
+                }
+                (catch_all, added_catch_all) => catch_all.or(added_catch_all),
+            };
+
             for child in added_node.children {
                 self.add_node_at_path(&format!("{}/{}", path, child.path_piece), child);
             }
+
+            for constrained_node in added_node.constrained_nodes {
+                self.add_node_at_path(
+                    &format!("{}/{}", path, constrained_node.path_piece),
+                    constrained_node,
+                );
+            }
         } else {
+            let case_insensitive = self.case_insensitive;
             let mut last_node = self;
             let split_vec = split.collect::<Vec<&str>>();
 
@@ -274,22 +496,31 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                 let piece = split_vec.get(i).unwrap().to_owned();
                 let children: &mut Vec<Node<T>> = &mut last_node.children;
 
-                if let Some(index) = children.iter().position(|n| n.path_piece == piece) {
+                if let Some(index) = children
+                    .iter()
+                    .position(|n| pieces_match(case_insensitive, &n.path_piece, piece))
+                {
                     last_node = children.get_mut(index).unwrap();
                 } else {
                     let next_node = Node::<T> {
                         path_piece: piece.to_owned(),
+                        case_insensitive,
                         ..Default::default()
                     };
 
                     children.push(next_node);
 
-                    last_node = children.get_mut(0).unwrap();
+                    last_node = children.last_mut().unwrap();
                 }
             }
 
             added_node.path_piece = split_vec.last().unwrap().to_string();
-            last_node.children.push(added_node);
+
+            if added_node.constrained_param.is_some() {
+                last_node.constrained_nodes.push(added_node);
+            } else {
+                last_node.children.push(added_node);
+            }
         }
     }
 
@@ -302,17 +533,32 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
         match path_piece {
             None => Some(self),
             Some(path_piece) => match path_piece.chars().next() {
+                Some(PARAM_ROUTE_LEADING_CHAR) if split_constrained_param(path_piece).is_some() => {
+                    for constrained_node in self.constrained_nodes.iter_mut() {
+                        if constrained_node.path_piece == path_piece {
+                            return constrained_node.get_node_at_split_path(split);
+                        }
+                    }
+
+                    None
+                }
                 Some(PARAM_ROUTE_LEADING_CHAR) => self
                     .wildcard_node
                     .as_mut()
                     .and_then(|w| w.get_node_at_split_path(split)),
+                Some(WILDCARD_ROUTE_ID) if path_piece.len() > 1 => self
+                    .catch_all
+                    .as_mut()
+                    .and_then(|(_, c)| c.get_node_at_split_path(split)),
                 Some(WILDCARD_ROUTE_ID) => self
                     .wildcard_node
                     .as_mut()
                     .and_then(|w| w.get_node_at_split_path(split)),
                 Some(_) => {
+                    let case_insensitive = self.case_insensitive;
+
                     for child in self.children.iter_mut() {
-                        if child.path_piece == path_piece {
+                        if pieces_match(case_insensitive, &child.path_piece, path_piece) {
                             return child.get_node_at_split_path(split);
                         }
                     }
@@ -338,11 +584,12 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
         // Merge child nodes, yes n^2, but this is on build so it's only done on init.
         for incoming_child in node.children.into_iter() {
             let path_piece = incoming_child.path_piece.clone();
+            let case_insensitive = self.case_insensitive;
 
             let child = self
                 .children
                 .iter_mut()
-                .find(|child| child.path_piece == path_piece);
+                .find(|child| pieces_match(case_insensitive, &child.path_piece, &path_piece));
 
             match child {
                 Some(child) => child.merge_node(incoming_child),
@@ -360,17 +607,31 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
         let path_piece = path.next();
 
         match path_piece {
+            // Outside of strict mode, a catch-all also matches its prefix without a trailing `/`
+            None if !self.is_leaf && !self.strict_mode && self.catch_all.is_some() => {
+                self.get_catch_all_value("", path).unwrap()
+            }
             None => NodeOutput {
                 value: &self.committed_middleware,
                 params: Params::default(),
                 path: "".to_owned(),
                 was_terminal_leaf: self.is_leaf,
+                allowed_methods: vec![],
+                trailing_slash_match: !self.is_leaf
+                    && self.strict_mode
+                    && self
+                        .children
+                        .iter()
+                        .any(|child| child.path_piece.is_empty() && child.is_leaf),
             },
             Some(path_piece) => {
-                // Check exact children
+                // Check exact children. If one matches, but not the rest of the path, then the
+                // constrained params, wildcard and catch-all at this level are checked before
+                // falling back to its result.
+                let mut static_match = None;
                 for child in self.children.iter() {
-                    if child.path_piece == path_piece {
-                        let mut res = child.get_value_at_split_path(path);
+                    if pieces_match(self.case_insensitive, &child.path_piece, path_piece) {
+                        let mut res = child.get_value_at_split_path(path.clone());
 
                         if let Some(param) = &self.param_name {
                             res.params.add(param, path_piece);
@@ -378,44 +639,105 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
 
                         if res.was_terminal_leaf {
                             return res;
-                        // If we have found a match, but it's non-terminal, then
-                        // we should check any wildcards at this level.
-                        } else if let Some(wildcard) = &self.wildcard_node {
-                            return NodeOutput {
-                                value: &wildcard.committed_middleware,
-                                params: Params::default(),
-                                path: "".to_owned(),
-                                was_terminal_leaf: wildcard.is_leaf,
-                            };
-                        // Otherwise just toss the result and hope something higher up picks
-                        // it up.
-                        } else {
-                            return res;
                         }
+
+                        static_match = Some(res);
+                        break;
                     }
                 }
 
-                // Check wildcard child
-                match self.wildcard_node.as_ref() {
-                    Some(wildcard_node) => {
-                        let mut res = wildcard_node.get_value_at_split_path(path);
-                        if let Some(param) = &self.param_name {
-                            res.params.add(param, path_piece);
+                // Check constrained param children, falling through to the next one if the
+                // remainder of the path doesn't match.
+                for constrained_node in self.constrained_nodes.iter() {
+                    if let Some((param, constraint)) = &constrained_node.constrained_param {
+                        if constraint.matches(path_piece) {
+                            let mut res = constrained_node.get_value_at_split_path(path.clone());
+
+                            if res.was_terminal_leaf {
+                                res.params.add(param, path_piece);
+                                return res;
+                            }
                         }
+                    }
+                }
+
+                // In strict mode, a trailing `/` only matches routes that were added with one,
+                // unless a wildcard or catch-all here would capture the empty piece.
+                if static_match.is_none()
+                    && self.strict_mode
+                    && path_piece.is_empty()
+                    && path.clone().next().is_none()
+                    && self.wildcard_node.is_none()
+                    && self.catch_all.is_none()
+                {
+                    return NodeOutput {
+                        value: &self.committed_middleware,
+                        params: Params::default(),
+                        path: "".to_owned(),
+                        was_terminal_leaf: false,
+                        allowed_methods: vec![],
+                        trailing_slash_match: self.is_leaf,
+                    };
+                }
+
+                // Check wildcard child
+                if let Some(wildcard_node) = self.wildcard_node.as_ref() {
+                    let mut res = wildcard_node.get_value_at_split_path(path.clone());
+                    if let Some(param) = &self.param_name {
+                        res.params.add(param, path_piece);
+                    }
 
-                        res
+                    if res.was_terminal_leaf || (self.catch_all.is_none() && static_match.is_none())
+                    {
+                        return res;
                     }
-                    None => NodeOutput {
+                }
+
+                // Check catch-all child
+                match self.get_catch_all_value(path_piece, path) {
+                    Some(res) => res,
+                    None => static_match.unwrap_or(NodeOutput {
                         value: &self.committed_middleware,
                         params: Params::default(),
                         path: "".to_owned(),
                         was_terminal_leaf: self.is_leaf,
-                    },
+                        allowed_methods: vec![],
+                        trailing_slash_match: false,
+                    }),
                 }
             }
         }
     }
 
+    /// Gets the value of the named catch-all at this level, if there is one, capturing the given
+    /// path piece and the rest of the path. Outside of strict mode, trailing slashes aren't
+    /// captured.
+    fn get_catch_all_value<'m, 'k: 'm, 'p>(
+        &'k self,
+        path_piece: &'p str,
+        path: Split<'p, char>,
+    ) -> Option<NodeOutput<'m, T>> {
+        let (param, catch_all_node) = self.catch_all.as_ref()?;
+
+        let rest = std::iter::once(path_piece)
+            .chain(path)
+            .collect::<Vec<&str>>()
+            .join("/");
+        let rest = if self.strict_mode {
+            &rest
+        } else {
+            rest.trim_end_matches('/')
+        };
+
+        let mut end_of_path = "".split('/');
+        let _ = end_of_path.next();
+
+        let mut res = catch_all_node.get_value_at_split_path(end_of_path);
+        res.params.add(param, rest);
+
+        Some(res)
+    }
+
     pub(crate) fn add_value_at_split_path(
         &mut self,
         mut path: Split<char>,
@@ -427,6 +749,11 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
         match path_piece {
             Some(path_piece) => {
                 match path_piece.chars().next() {
+                    Some(PARAM_ROUTE_LEADING_CHAR)
+                        if split_constrained_param(path_piece).is_some() =>
+                    {
+                        self.add_value_at_constrained_path(path_piece, path, value, is_leaf);
+                    }
                     Some(PARAM_ROUTE_LEADING_CHAR) => match self.wildcard_node.as_mut() {
                         Some(wildcard_node) => {
                             wildcard_node.add_value_at_split_path(path, value, is_leaf);
@@ -436,6 +763,7 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                             let mut wildcard_node = Node::<T> {
                                 path_piece: WILDCARD_ROUTE_ID.to_string(),
                                 strict_mode: self.strict_mode,
+                                case_insensitive: self.case_insensitive,
                                 ..Default::default()
                             };
                             wildcard_node.add_value_at_split_path(path, value, is_leaf);
@@ -444,6 +772,9 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                             self.wildcard_node = Some(Box::new(wildcard_node));
                         }
                     },
+                    Some(WILDCARD_ROUTE_ID) if path_piece.len() > 1 => {
+                        self.add_value_at_catch_all_path(path_piece, path, value, is_leaf);
+                    }
                     Some(WILDCARD_ROUTE_ID) => match self.wildcard_node.as_mut() {
                         Some(wildcard_node) => {
                             wildcard_node.add_value_at_split_path(path, value, is_leaf);
@@ -452,6 +783,7 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                             let mut wildcard_node = Node::<T> {
                                 path_piece: WILDCARD_ROUTE_ID.to_string(),
                                 strict_mode: self.strict_mode,
+                                case_insensitive: self.case_insensitive,
                                 ..Default::default()
                             };
                             wildcard_node.add_value_at_split_path(path, value, is_leaf);
@@ -466,23 +798,20 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
                     None => {
                         if self.strict_mode {
                             self.add_value_at_split_path_helper(path_piece, path, value, is_leaf);
+                        } else if is_leaf {
+                            self.is_leaf = true;
+                            self.value = Some(value);
                         } else {
-                            self.is_leaf = is_leaf;
-
-                            if self.is_leaf {
-                                self.value = Some(value)
-                            } else {
-                                self.non_leaf_value = Some(value);
-                            }
+                            self.non_leaf_value = Some(value);
                         }
                     }
                 };
             }
+            // Adding middleware to a node shouldn't stop an existing route there from being a leaf.
             None => {
-                self.is_leaf = is_leaf;
-
-                if self.is_leaf {
-                    self.value = Some(value)
+                if is_leaf {
+                    self.is_leaf = true;
+                    self.value = Some(value);
                 } else {
                     self.non_leaf_value = Some(value);
                 }
@@ -500,13 +829,14 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
         let existing_index = self
             .children
             .iter()
-            .position(|n| n.path_piece == path_piece);
+            .position(|n| pieces_match(self.case_insensitive, &n.path_piece, path_piece));
 
         // Note: This operation no longer preserves order.
         let mut child_node = match existing_index {
             Some(i) => self.children.remove(i),
             None => Node::<T> {
                 strict_mode: self.strict_mode,
+                case_insensitive: self.case_insensitive,
                 ..Node::default()
             },
         };
@@ -516,6 +846,151 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
         self.children.push(child_node);
     }
 
+    fn add_value_at_catch_all_path(
+        &mut self,
+        path_piece: &str,
+        mut path: Split<char>,
+        value: MiddlewareTuple<T>,
+        is_leaf: bool,
+    ) {
+        if path.clone().any(|piece| !piece.is_empty()) {
+            panic!(
+                "The catch-all '{}' must be the last piece of the route it's in",
+                path_piece
+            );
+        }
+
+        let name = path_piece[1..].to_string();
+        let strict_mode = self.strict_mode;
+        let case_insensitive = self.case_insensitive;
+        let (existing_name, catch_all_node) = self.catch_all.get_or_insert_with(|| {
+            (
+                name.clone(),
+                Box::new(Node::<T> {
+                    path_piece: path_piece.to_owned(),
+                    strict_mode,
+                    case_insensitive,
+                    ..Node::default()
+                }),
+            )
+        });
+
+        *existing_name = name;
+        let _ = path.next();
+        catch_all_node.add_value_at_split_path(path, value, is_leaf);
+    }
+
+    fn add_value_at_constrained_path(
+        &mut self,
+        path_piece: &str,
+        path: Split<char>,
+        value: MiddlewareTuple<T>,
+        is_leaf: bool,
+    ) {
+        let existing_index = self
+            .constrained_nodes
+            .iter()
+            .position(|n| n.path_piece == path_piece);
+
+        match existing_index {
+            Some(i) => self.constrained_nodes[i].add_value_at_split_path(path, value, is_leaf),
+            None => {
+                let (param, constraint) = split_constrained_param(path_piece).unwrap();
+                let mut constrained_node = Node::<T> {
+                    path_piece: path_piece.to_owned(),
+                    constrained_param: Some((param.to_owned(), ParamConstraint::parse(constraint))),
+                    strict_mode: self.strict_mode,
+                    case_insensitive: self.case_insensitive,
+                    ..Node::default()
+                };
+                constrained_node.add_value_at_split_path(path, value, is_leaf);
+
+                self.constrained_nodes.push(constrained_node);
+            }
+        }
+    }
+
+    /// Sets whether static path pieces are matched ignoring ASCII case, for this node and every
+    /// node below it.
+    pub(crate) fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+
+        for child in self
+            .children
+            .iter_mut()
+            .chain(self.constrained_nodes.iter_mut())
+        {
+            child.set_case_insensitive(case_insensitive);
+        }
+        if let Some(wildcard_node) = self.wildcard_node.as_mut() {
+            wildcard_node.set_case_insensitive(case_insensitive);
+        }
+        if let Some((_, catch_all)) = self.catch_all.as_mut() {
+            catch_all.set_case_insensitive(case_insensitive);
+        }
+    }
+
+    /// Lists the routes that can be matched in the tree.
+    pub fn routes(&self) -> Vec<RouteEntry> {
+        let mut routes = vec![];
+        self.collect_routes("", &[], &mut routes);
+
+        routes
+    }
+
+    fn collect_routes(&self, path: &str, params: &[String], routes: &mut Vec<RouteEntry>) {
+        if self.is_leaf {
+            if let Some(value) = self.committed_value.as_ref().or(self.value.as_ref()) {
+                routes.push(RouteEntry {
+                    path: if path.is_empty() { "/" } else { path }.to_owned(),
+                    params: params.to_vec(),
+                    middleware_count: value.count(),
+                });
+            }
+        }
+
+        for child in self.children.iter() {
+            child.collect_routes(&format!("{}/{}", path, child.path_piece), params, routes);
+        }
+
+        for constrained_node in self.constrained_nodes.iter() {
+            let mut params = params.to_vec();
+            if let Some((param, _)) = &constrained_node.constrained_param {
+                params.push(param.to_owned());
+            }
+
+            constrained_node.collect_routes(
+                &format!("{}/{}", path, constrained_node.path_piece),
+                &params,
+                routes,
+            );
+        }
+
+        if let Some(wildcard_node) = &self.wildcard_node {
+            let mut params = params.to_vec();
+            let path_piece = match &self.param_name {
+                Some(param) => {
+                    params.push(param.to_owned());
+                    format!("{}{}", PARAM_ROUTE_LEADING_CHAR, param)
+                }
+                None => WILDCARD_ROUTE_ID.to_string(),
+            };
+
+            wildcard_node.collect_routes(&format!("{}/{}", path, path_piece), &params, routes);
+        }
+
+        if let Some((param, catch_all_node)) = &self.catch_all {
+            let mut params = params.to_vec();
+            params.push(param.to_owned());
+
+            catch_all_node.collect_routes(
+                &format!("{}/{}", path, catch_all_node.path_piece),
+                &params,
+                routes,
+            );
+        }
+    }
+
     pub(crate) fn enumerate(
         &self,
         path: &str,
@@ -548,6 +1023,12 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
 
         for (path, committed_value, _value, _wildcard_exists) in enumerations {
             if let Some(value) = committed_value {
+                let path = if committed.case_insensitive {
+                    path.to_ascii_lowercase()
+                } else {
+                    path
+                };
+
                 committed.fastmatch_map.insert(path, value.middleware());
             }
         }
@@ -569,9 +1050,16 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
             .into_iter()
             .map(|c| c.commit_inner(updated_collected_middleware.clone()))
             .collect();
+        let constrained_nodes = self
+            .constrained_nodes
+            .into_iter()
+            .map(|c| c.commit_inner(updated_collected_middleware.clone()))
+            .collect();
         let has_committed_middleware = self.value.is_some();
+        let isolated = self.isolated;
+        let inherited_middleware = updated_collected_middleware.clone().filter(|_| !isolated);
         let (committed, committed_tuple) = match self.value.take() {
-            Some(v) => match updated_collected_middleware.clone() {
+            Some(v) => match inherited_middleware {
                 Some(updated_collected_middleware) => {
                     let combined = updated_collected_middleware.combine(v);
 
@@ -587,16 +1075,26 @@ impl<T: 'static + Context + Clone + Send> Node<T> {
             wildcard_node: self
                 .wildcard_node
                 .map(|n| Box::new(n.commit_inner(updated_collected_middleware.clone()))),
+            catch_all: self.catch_all.map(|(name, n)| {
+                (
+                    name,
+                    Box::new(n.commit_inner(updated_collected_middleware.clone())),
+                )
+            }),
             path_piece: self.path_piece,
             param_name: self.param_name,
             children,
+            constrained_nodes,
+            constrained_param: self.constrained_param,
             committed_middleware: committed,
             committed_value: committed_tuple,
             has_committed_middleware,
             is_leaf: self.is_leaf,
+            isolated: self.isolated,
             non_leaf_value: None,
             fastmatch_map: FnvHashMap::default(),
             strict_mode: self.strict_mode,
+            case_insensitive: self.case_insensitive,
         }
     }
 }
@@ -656,7 +1154,7 @@ impl<T: Clone + Send> Node<T> {
             }
         );
 
-        for child in self.children.iter() {
+        for child in self.children.iter().chain(self.constrained_nodes.iter()) {
             val = format!(
                 "{}\n{}",
                 val,
@@ -672,6 +1170,14 @@ impl<T: Clone + Send> Node<T> {
             );
         }
 
+        if let Some((_, ref catch_all_node)) = self.catch_all {
+            val = format!(
+                "{}\n{}",
+                val,
+                catch_all_node.print_with_indentation(indentation_level + 1)
+            );
+        }
+
         val
     }
 }
@@ -1180,4 +1686,243 @@ pub mod test {
                 );
             });
     }
+
+    #[test]
+    fn it_should_match_static_pieces_ignoring_case_when_case_insensitive() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        let mut root: Node<i32> = Node {
+            case_insensitive: true,
+            ..Node::default()
+        };
+
+        root.add_value_at_path("/users/:name", MiddlewareTuple::A(pinbox!(i32, f1)));
+        root.add_value_at_path("/Admin", MiddlewareTuple::A(pinbox!(i32, f1)));
+
+        let committed = root.commit();
+
+        let node = committed.get_value_at_path("/USERS/Jane".to_owned());
+        assert!(node.was_terminal_leaf);
+        assert_eq!(node.params.get("name").unwrap().param, "Jane");
+        assert!(
+            committed
+                .get_value_at_path("/admin".to_owned())
+                .was_terminal_leaf
+        );
+        assert!(
+            committed
+                .get_value_at_path("/ADMIN".to_owned())
+                .was_terminal_leaf
+        );
+
+        let mut root: Node<i32> = Node::default();
+        root.add_value_at_path("/users", MiddlewareTuple::A(pinbox!(i32, f1)));
+        assert!(
+            !root
+                .commit()
+                .get_value_at_path("/Users".to_owned())
+                .was_terminal_leaf
+        );
+    }
+
+    #[test]
+    fn it_should_fall_through_constrained_params_that_do_not_match() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        async fn f2(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 2)
+        }
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let mut root: Node<i32> = Node::default();
+
+                root.add_value_at_path("/users/:id<u64>", MiddlewareTuple::A(pinbox!(i32, f1)));
+                root.add_value_at_path("/users/:name", MiddlewareTuple::A(pinbox!(i32, f2)));
+                let committed = root.commit();
+
+                let node = committed.get_value_at_path("/users/42".to_owned());
+                assert_eq!(node.params.get_as::<u64>("id"), Some(42));
+                assert!((node.value)(0_i32).await.unwrap() == 1);
+
+                let node = committed.get_value_at_path("/users/abc".to_owned());
+                assert!(node.params.get("id").is_none());
+                assert!((node.value)(0_i32).await.unwrap() == 2);
+            });
+    }
+
+    #[test]
+    fn it_should_fall_back_to_constrained_params_when_a_static_match_is_not_terminal() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        async fn f2(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 2)
+        }
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let mut root: Node<i32> = Node::default();
+
+                root.add_value_at_path("/a/:id<u64>", MiddlewareTuple::A(pinbox!(i32, f1)));
+                root.add_value_at_path("/a/1/x", MiddlewareTuple::A(pinbox!(i32, f2)));
+                let committed = root.commit();
+
+                let node = committed.get_value_at_path("/a/1".to_owned());
+                assert!(node.was_terminal_leaf);
+                assert_eq!(node.params.get_as::<u64>("id"), Some(1));
+                assert!((node.value)(0_i32).await.unwrap() == 1);
+
+                let node = committed.get_value_at_path("/a/1/x".to_owned());
+                assert!(node.was_terminal_leaf);
+                assert!((node.value)(0_i32).await.unwrap() == 2);
+            });
+    }
+
+    #[test]
+    fn it_should_match_regex_constrained_params() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        let mut root: Node<i32> = Node::default();
+
+        root.add_value_at_path(
+            "/files/:name<[a-z]+\\.txt>/raw",
+            MiddlewareTuple::A(pinbox!(i32, f1)),
+        );
+        let committed = root.commit();
+
+        let node = committed.get_value_at_path("/files/notes.txt/raw".to_owned());
+        assert!(node.was_terminal_leaf);
+        assert_eq!(node.params.get("name").unwrap().param, "notes.txt");
+
+        let node = committed.get_value_at_path("/files/notes.md/raw".to_owned());
+        assert!(!node.was_terminal_leaf);
+    }
+
+    #[test]
+    fn it_should_capture_the_rest_of_the_path_in_a_named_catch_all() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        async fn f2(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 2)
+        }
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let mut root: Node<i32> = Node::default();
+
+                root.add_value_at_path("/static/*rest", MiddlewareTuple::A(pinbox!(i32, f1)));
+                root.add_value_at_path(
+                    "/static/css/main.css",
+                    MiddlewareTuple::A(pinbox!(i32, f2)),
+                );
+                let committed = root.commit();
+
+                let node = committed.get_value_at_path("/static/css/other.css?v=1".to_owned());
+                assert_eq!(node.params.get("rest").unwrap().param, "css/other.css");
+                assert!((node.value)(0_i32).await.unwrap() == 1);
+
+                let node = committed.get_value_at_path("/static/css/main.css".to_owned());
+                assert!((node.value)(0_i32).await.unwrap() == 2);
+
+                let node = committed.get_value_at_path("/static/a/b/".to_owned());
+                assert_eq!(node.params.get("rest").unwrap().param, "a/b");
+
+                let node = committed.get_value_at_path("/static".to_owned());
+                assert!(node.was_terminal_leaf);
+                assert_eq!(node.params.get("rest").unwrap().param, "");
+            });
+    }
+
+    #[test]
+    fn it_should_respect_strict_mode_for_named_catch_alls() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        let mut root: Node<i32> = Node {
+            strict_mode: true,
+            ..Node::default()
+        };
+
+        root.add_value_at_path("/static/*rest", MiddlewareTuple::A(pinbox!(i32, f1)));
+        let committed = root.commit();
+
+        let node = committed.get_value_at_path("/static/a/b/".to_owned());
+        assert_eq!(node.params.get("rest").unwrap().param, "a/b/");
+
+        assert!(
+            !committed
+                .get_value_at_path("/static".to_owned())
+                .was_terminal_leaf
+        );
+    }
+
+    #[test]
+    fn it_should_list_routes() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        let mut root: Node<i32> = Node::default();
+
+        root.add_value_at_path("/", MiddlewareTuple::A(pinbox!(i32, f1)));
+        root.add_value_at_path("/a/:id/b", MiddlewareTuple::A(pinbox!(i32, f1)));
+        root.add_value_at_path("/a/:id<u64>", MiddlewareTuple::A(pinbox!(i32, f1)));
+        root.add_value_at_path("/static/*rest", MiddlewareTuple::A(pinbox!(i32, f1)));
+        root.add_non_leaf_value_at_path("/a", MiddlewareTuple::A(pinbox!(i32, f1)));
+
+        let mut routes = root
+            .commit()
+            .routes()
+            .into_iter()
+            .map(|r| (r.path, r.params, r.middleware_count))
+            .collect::<Vec<(String, Vec<String>, usize)>>();
+        routes.sort();
+
+        assert_eq!(
+            routes,
+            vec![
+                ("/".to_owned(), vec![], 1),
+                ("/a/:id/b".to_owned(), vec!["id".to_owned()], 2),
+                ("/a/:id<u64>".to_owned(), vec!["id".to_owned()], 2),
+                ("/static/*rest".to_owned(), vec!["rest".to_owned()], 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_add_nodes_at_new_nested_paths_next_to_existing_children() {
+        async fn f1(a: i32, _b: NextFn<i32>) -> Result<i32, ThrusterError<i32>> {
+            Ok(a + 1)
+        }
+
+        let mut root: Node<i32> = Node::default();
+        root.add_value_at_path("/a/c", MiddlewareTuple::A(pinbox!(i32, f1)));
+
+        let mut mounted: Node<i32> = Node::default();
+        mounted.add_value_at_path("/d", MiddlewareTuple::A(pinbox!(i32, f1)));
+        root.add_node_at_path("/b/c", mounted);
+
+        let mut routes = root
+            .routes()
+            .into_iter()
+            .map(|r| r.path)
+            .collect::<Vec<String>>();
+        routes.sort();
+
+        assert_eq!(routes, vec!["/a/c".to_owned(), "/b/c/d".to_owned()]);
+    }
 }
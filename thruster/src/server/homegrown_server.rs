@@ -1,18 +1,23 @@
 use crate::ReusableBoxFuture;
 use async_trait::async_trait;
-use futures::{FutureExt, SinkExt, StreamExt};
-use socket2::{Domain, Socket, Type};
+use futures::future::{self, Either};
+use futures::{SinkExt, StreamExt};
+use std::future::Future;
 use std::net::ToSocketAddrs;
-use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use std::task::Poll;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
 use tokio_stream::wrappers::TcpListenerStream;
 use tokio_util::codec::Framed;
 
-use crate::app::App;
+use crate::app::{App, SwappableApp};
 use crate::core::context::Context;
+use crate::core::errors::{RequestError, ServerError};
 use crate::core::http::Http;
-use crate::core::request::Request;
-use crate::core::response::Response;
+use crate::core::request::{Request, RequestLimits};
+use crate::core::response::{BodyChunk, Response};
 
 // use std::thread;
 // use num_cpus;
@@ -20,18 +25,85 @@ use crate::core::response::Response;
 // #[cfg(not(windows))]
 // use net2::unix::UnixTcpBuilderExt;
 
+use crate::server::listeners::{bind_address, BindOptions, Listeners};
+use crate::server::shutdown::{accept_until, Shutdown, Watch, DEFAULT_SHUTDOWN_TIMEOUT};
+use crate::server::thruster_server::{expect_built, resolve_address};
 use crate::server::ThrusterServer;
 
 pub struct Server<
     T: 'static + Context<Response = Response> + Clone + Send + Sync,
     S: 'static + Send + Sync,
 > {
-    app: Arc<App<Request, T, S>>,
+    app: SwappableApp<Request, T, S>,
+    connection: ConnectionConfig,
+    shutdown_timeout: Duration,
+}
+
+/// How long an idle connection is kept open waiting for another request, unless set via
+/// `with_keep_alive_timeout`.
+const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(75);
+
+/// How long a request can take to arrive once it's started, unless set via `with_read_timeout`.
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The options each connection is served with.
+#[derive(Clone, Copy)]
+struct ConnectionConfig {
+    request_limits: RequestLimits,
+    keep_alive_timeout: Duration,
+    read_timeout: Duration,
 }
 
 impl<T: 'static + Context<Response = Response> + Clone + Send + Sync, S: 'static + Send + Sync>
     Server<T, S>
 {
+    ///
+    /// Creates a server for an app that can be swapped out while the server is running
+    ///
+    pub fn new_swappable(app: SwappableApp<Request, T, S>) -> Self {
+        Server {
+            app,
+            connection: ConnectionConfig {
+                request_limits: RequestLimits::default(),
+                keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+                read_timeout: DEFAULT_READ_TIMEOUT,
+            },
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    /// Sets the limits on the size of requests, such as the most headers or the largest body, that
+    /// the server accepts. Requests over them are rejected with a 413, 414 or 431.
+    pub fn with_request_limits(mut self, request_limits: RequestLimits) -> Self {
+        self.connection.request_limits = request_limits;
+
+        self
+    }
+
+    /// Sets how long a connection is kept open without receiving another request before it's
+    /// closed. Defaults to 75 seconds.
+    pub fn with_keep_alive_timeout(mut self, keep_alive_timeout: Duration) -> Self {
+        self.connection.keep_alive_timeout = keep_alive_timeout;
+
+        self
+    }
+
+    /// Sets how long a request can take to arrive once its first bytes have, before the
+    /// connection is closed. Defaults to 30 seconds.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.connection.read_timeout = read_timeout;
+
+        self
+    }
+
+    /// Sets how long `build_with_shutdown` waits for open connections to finish after the
+    /// shutdown signal. Defaults to 30 seconds.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+
+        self
+    }
+
     ///
     /// Starts the app with the default tokio runtime execution model
     ///
@@ -56,7 +128,8 @@ impl<T: 'static + Context<Response = Response> + Clone + Send + Sync, S: 'static
         let addr = (host, port).to_socket_addrs().unwrap().next().unwrap();
         let mut threads = Vec::new();
 
-        let arc_app = Arc::new(self.app);
+        let arc_app = self.app;
+        let connection = self.connection;
 
         for _ in 0..num_cpus::get() {
             let arc_app = arc_app.clone();
@@ -67,24 +140,19 @@ impl<T: 'static + Context<Response = Response> + Clone + Send + Sync, S: 'static
                     .unwrap();
 
                 let server = async move {
-                    let listener = {
-                        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
-
-                        let address = addr.into();
-                        socket.set_reuse_address(true).unwrap();
-                        #[cfg(unix)]
-                        socket.set_reuse_port(true).unwrap();
-                        socket.bind(&address).unwrap();
-                        socket.listen(1024).unwrap();
-                        socket.set_nonblocking(true).unwrap();
-
-                        let listener: std::net::TcpListener = socket.into();
-                        tokio::net::TcpListener::from_std(listener).unwrap()
-                    };
+                    let shutdown = Shutdown::new();
+                    let listener = bind_address(addr, true, BindOptions::default()).unwrap();
+                    listener.set_nonblocking(true).unwrap();
+                    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
 
                     TcpListenerStream::new(listener)
                         .for_each(move |socket| {
-                            process(Arc::clone(&arc_app), socket.unwrap());
+                            process(
+                                arc_app.clone(),
+                                socket.unwrap(),
+                                connection,
+                                shutdown.watch(),
+                            );
                             async {}
                         })
                         .await;
@@ -109,30 +177,62 @@ impl<T: Context<Response = Response> + Clone + Send + Sync, S: 'static + Send +
     type Request = Request;
     type State = S;
 
-    fn new(mut app: App<Self::Request, T, S>) -> Self {
-        app = app.commit();
-
-        Server { app: Arc::new(app) }
+    fn new(app: App<Self::Request, T, S>) -> Self {
+        Self::new_swappable(SwappableApp::new(app))
     }
 
     fn build(self, host: &str, port: u16) -> ReusableBoxFuture<()> {
-        let addr = (host, port).to_socket_addrs().unwrap().next().unwrap();
+        expect_built(self.try_build(host, port))
+    }
+
+    fn try_build(self, host: &str, port: u16) -> ReusableBoxFuture<Result<(), ServerError>> {
+        self.build_with_shutdown(host, port, future::pending())
+    }
 
+    fn build_with_shutdown(
+        self,
+        host: &str,
+        port: u16,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
+        let listeners = resolve_address(host, port).and_then(|addr| Listeners::bind(&[addr]));
+
+        match listeners {
+            Ok(listeners) => self.build_with_listeners(listeners, signal),
+            Err(e) => ReusableBoxFuture::new(future::ready(Err(e))),
+        }
+    }
+}
+
+impl<T: Context<Response = Response> + Clone + Send + Sync, S: 'static + Send + Sync> Server<T, S> {
+    /// Builds the server like `build_with_shutdown`, but accepts connections on the given
+    /// listeners rather than binding to a host and port.
+    pub fn build_with_listeners(
+        self,
+        listeners: Listeners,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
         // self.app._route_parser.optimize();
 
         let arc_app = self.app;
-        let listener_fut = TcpListener::bind(addr).then(move |listener| {
-            TcpListenerStream::new(listener.unwrap()).for_each(move |res| {
+        let connection = self.connection;
+        let shutdown_timeout = self.shutdown_timeout;
+        ReusableBoxFuture::new(async move {
+            let mut incoming = listeners.incoming()?;
+            let shutdown = Shutdown::new();
+            futures::pin_mut!(signal);
+
+            while let Some(Some(res)) = accept_until(incoming.next(), signal.as_mut()).await {
                 if let Ok(stream) = res {
                     let cloned = arc_app.clone();
-                    tokio::spawn(process(cloned, stream));
+                    tokio::spawn(process(cloned, stream, connection, shutdown.watch()));
                 }
+            }
 
-                async {}
-            })
-        });
+            shutdown.drain(shutdown_timeout).await;
 
-        ReusableBoxFuture::new(listener_fut)
+            Ok(())
+        })
     }
 }
 
@@ -141,29 +241,112 @@ struct _Error {
 }
 
 fn process<T: Context<Response = Response> + Clone + Send + Sync, S: 'static + Send + Sync>(
-    app: Arc<App<Request, T, S>>,
+    app: SwappableApp<Request, T, S>,
     socket: TcpStream,
+    connection: ConnectionConfig,
+    mut watch: Watch,
 ) -> ReusableBoxFuture<Result<(), _Error>> {
     ReusableBoxFuture::new(async move {
-        let mut framed = Framed::new(socket, Http);
+        let mut framed = Framed::new(socket, Http::with_limits(connection.request_limits));
+
+        loop {
+            // Once the server is shutting down, stop waiting for requests on idle connections
+            let incoming = {
+                let incoming = next_incoming(&mut framed, &connection);
+                let signalled = watch.signalled();
+                futures::pin_mut!(incoming, signalled);
+
+                match future::select(incoming, signalled).await {
+                    Either::Left((incoming, _)) => incoming,
+                    Either::Right(_) => break,
+                }
+            };
+
+            let request = match incoming {
+                Incoming::Request(request) => request,
+                Incoming::Continue => {
+                    framed
+                        .get_mut()
+                        .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                        .await
+                        .map_err(|e| _Error {
+                            _message: e.to_string(),
+                        })?;
+
+                    continue;
+                }
+                Incoming::Closed => break,
+            };
 
-        while let Some(request) = framed.next().await {
             match request {
                 Ok(request) => {
-                    let path = request.path().to_owned();
+                    let app = app.load();
                     let method = &request.method().to_owned();
-                    let matched = app.resolve_from_method_and_path(method, path);
-                    let response = app.resolve(request, matched).await.map_err(|e| _Error {
+                    let version = request.version();
+                    let keep_alive = request.keep_alive();
+                    let matched = app.resolve_request(&request);
+                    let mut response = app.resolve(request, matched).await.map_err(|e| _Error {
                         _message: e.to_string(),
                     })?;
+                    response.omit_body(method == "HEAD").version(version);
+                    let stream = response
+                        .take_body_stream()
+                        .filter(|_| method != "HEAD" && response.allows_body());
+
+                    // HTTP/1.0 clients tell that a streamed body has ended by the connection
+                    // closing
+                    let keep_alive = keep_alive
+                        && !response.closes_connection()
+                        && !(version == 0 && stream.is_some());
+                    if !keep_alive && !response.closes_connection() {
+                        response.header("Connection", "close");
+                    } else if keep_alive && version == 0 {
+                        response.header("Connection", "keep-alive");
+                    }
+
                     framed.send(response).await.map_err(|e| _Error {
                         _message: e.to_string(),
                     })?;
+
+                    if let Some(mut stream) = stream {
+                        // Each chunk is flushed as it's sent, so that events aren't held back
+                        while let Some(data) = stream.next().await {
+                            let chunk = match version {
+                                0 => BodyChunk::Unframed(data),
+                                _ => BodyChunk::Data(data),
+                            };
+
+                            framed.send(chunk).await.map_err(|e| _Error {
+                                _message: e.to_string(),
+                            })?;
+                        }
+
+                        if version > 0 {
+                            framed.send(BodyChunk::End).await.map_err(|e| _Error {
+                                _message: e.to_string(),
+                            })?;
+                        }
+                    }
+
+                    if !keep_alive {
+                        break;
+                    }
                 }
                 Err(e) => {
+                    // The rest of the connection can't be trusted to be a valid request, so it's
+                    // closed after the error response
+                    if let Some((code, reason)) = e.status() {
+                        let mut response = Response::new();
+                        response
+                            .status_code(code, reason)
+                            .header("Connection", "close")
+                            .body(reason);
+                        let _ = framed.send(response).await;
+                    }
+
                     return Err(_Error {
                         _message: e.to_string(),
-                    })
+                    });
                 }
             }
         }
@@ -171,3 +354,385 @@ fn process<T: Context<Response = Response> + Clone + Send + Sync, S: 'static + S
         Ok(())
     })
 }
+
+/// What a connection receives next. It's only ever held briefly, so it isn't worth boxing the
+/// request.
+#[allow(clippy::large_enum_variant)]
+enum Incoming {
+    Request(Result<Request, RequestError>),
+    /// The head of a request with `Expect: 100-continue` has arrived, and the client is waiting
+    /// to be told to send its body.
+    Continue,
+    /// The connection was closed by the client, or timed out.
+    Closed,
+}
+
+/// Waits for the next request on the connection, for up to the keep-alive timeout while it's
+/// idle, then up to the read timeout once a request starts arriving.
+async fn next_incoming(
+    framed: &mut Framed<TcpStream, Http>,
+    connection: &ConnectionConfig,
+) -> Incoming {
+    let mut reading = !framed.read_buffer().is_empty();
+    let timeout = if reading {
+        connection.read_timeout
+    } else {
+        connection.keep_alive_timeout
+    };
+    let deadline = tokio::time::sleep(timeout);
+    futures::pin_mut!(deadline);
+
+    future::poll_fn(|cx| {
+        match framed.poll_next_unpin(cx) {
+            Poll::Ready(Some(request)) => return Poll::Ready(Incoming::Request(request)),
+            Poll::Ready(None) => return Poll::Ready(Incoming::Closed),
+            Poll::Pending => (),
+        }
+
+        if framed.codec_mut().take_continue() {
+            return Poll::Ready(Incoming::Continue);
+        }
+
+        if !reading && !framed.read_buffer().is_empty() {
+            reading = true;
+            deadline
+                .as_mut()
+                .reset(Instant::now() + connection.read_timeout);
+        }
+
+        deadline.as_mut().poll(cx).map(|_| Incoming::Closed)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::basic_context::BasicContext;
+    use crate::core::errors::ThrusterError;
+    use crate::parser::middleware_traits::{MiddlewareTuple, NextFn};
+    use crate::pinbox;
+    use bytes::Bytes;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    async fn slow(
+        mut context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        context.body("slow");
+
+        Ok(context)
+    }
+
+    async fn streamed(
+        mut context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        context.body_stream(futures::stream::iter(vec![
+            Bytes::from("hello"),
+            Bytes::from(", world"),
+        ]));
+
+        Ok(context)
+    }
+
+    #[test]
+    fn it_should_stream_bodies_in_chunks() {
+        let listeners = Listeners::bind(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let addr = listeners.local_addrs().unwrap()[0];
+        let app = App::<Request, BasicContext, ()>::new_basic().get(
+            "/streamed",
+            MiddlewareTuple::A(pinbox!(BasicContext, streamed)),
+        );
+        let (signal, signalled) = oneshot::channel::<()>();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let server =
+                    tokio::spawn(Server::<BasicContext, ()>::new(app).build_with_listeners(
+                        listeners,
+                        async move {
+                            let _ = signalled.await;
+                        },
+                    ));
+
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                stream
+                    .write_all(b"GET /streamed HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .await
+                    .unwrap();
+
+                let mut response = vec![];
+                while !response.ends_with(b"0\r\n\r\n") {
+                    let mut read = vec![0; 1024];
+                    let len = stream.read(&mut read).await.unwrap();
+                    assert_ne!(len, 0);
+                    response.extend_from_slice(&read[..len]);
+                }
+                let response = String::from_utf8(response).unwrap();
+
+                assert!(response.contains("Transfer-Encoding: chunked\r\n"));
+                assert!(response.ends_with("\r\n\r\n5\r\nhello\r\n7\r\n, world\r\n0\r\n\r\n"));
+
+                drop(stream);
+                signal.send(()).unwrap();
+                server.await.unwrap().unwrap();
+            });
+    }
+
+    #[test]
+    fn it_should_respond_to_requests_over_the_limits_and_close_the_connection() {
+        let listeners = Listeners::bind(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let addr = listeners.local_addrs().unwrap()[0];
+        let app = App::<Request, BasicContext, ()>::new_basic();
+        let (signal, signalled) = oneshot::channel::<()>();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let server = tokio::spawn(
+                    Server::<BasicContext, ()>::new(app)
+                        .with_request_limits(RequestLimits {
+                            max_body_size: 4,
+                            ..RequestLimits::default()
+                        })
+                        .build_with_listeners(listeners, async move {
+                            let _ = signalled.await;
+                        }),
+                );
+
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                stream
+                    .write_all(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello")
+                    .await
+                    .unwrap();
+
+                let mut response = vec![];
+                stream.read_to_end(&mut response).await.unwrap();
+                let response = String::from_utf8(response).unwrap();
+
+                assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+                assert!(response.contains("Connection: close\r\n"));
+
+                signal.send(()).unwrap();
+                server.await.unwrap().unwrap();
+            });
+    }
+
+    async fn echo(
+        mut context: BasicContext,
+        _next: NextFn<BasicContext>,
+    ) -> Result<BasicContext, ThrusterError<BasicContext>> {
+        let body = context.request.body().to_owned();
+        context.body(&body);
+
+        Ok(context)
+    }
+
+    /// Serves an app that echoes request bodies at `/echo` on a local port, until the given test
+    /// against its address finishes.
+    fn with_echo_server<F: Future<Output = ()>>(
+        server: impl FnOnce(Server<BasicContext, ()>) -> Server<BasicContext, ()>,
+        test: impl FnOnce(std::net::SocketAddr) -> F,
+    ) {
+        let listeners = Listeners::bind(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let addr = listeners.local_addrs().unwrap()[0];
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .post("/echo", MiddlewareTuple::A(pinbox!(BasicContext, echo)));
+        let (signal, signalled) = oneshot::channel::<()>();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let server = tokio::spawn(server(Server::new(app)).build_with_listeners(
+                    listeners,
+                    async move {
+                        let _ = signalled.await;
+                    },
+                ));
+
+                test(addr).await;
+
+                signal.send(()).unwrap();
+                server.await.unwrap().unwrap();
+            });
+    }
+
+    #[test]
+    fn it_should_close_connections_unless_they_are_kept_alive() {
+        with_echo_server(
+            |server| server,
+            |addr| async move {
+                for (request, expected_start, closed) in [
+                    ("POST /echo HTTP/1.0\r\n\r\n", "HTTP/1.0 200", true),
+                    (
+                        "POST /echo HTTP/1.1\r\nConnection: close\r\n\r\n",
+                        "HTTP/1.1 200",
+                        true,
+                    ),
+                    (
+                        "POST /echo HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n",
+                        "HTTP/1.0 200",
+                        false,
+                    ),
+                    ("POST /echo HTTP/1.1\r\n\r\n", "HTTP/1.1 200", false),
+                ] {
+                    let mut stream = TcpStream::connect(addr).await.unwrap();
+                    stream.write_all(request.as_bytes()).await.unwrap();
+
+                    let mut response = vec![0; 1024];
+                    let len = stream.read(&mut response).await.unwrap();
+                    let response = String::from_utf8_lossy(&response[..len]).into_owned();
+                    assert!(response.starts_with(expected_start), "{}", response);
+                    assert_eq!(response.contains("Connection: close\r\n"), closed);
+
+                    // The connection is either closed, or still open for another request
+                    let read =
+                        tokio::time::timeout(Duration::from_millis(100), stream.read(&mut [0; 16]))
+                            .await;
+                    assert_eq!(matches!(read, Ok(Ok(0))), closed, "{}", request);
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn it_should_send_100_continue_when_a_request_expects_it() {
+        with_echo_server(
+            |server| server,
+            |addr| async move {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                stream
+                    .write_all(
+                        b"POST /echo HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+
+                let mut response = vec![0; 1024];
+                let len = stream.read(&mut response).await.unwrap();
+                assert_eq!(&response[..len], b"HTTP/1.1 100 Continue\r\n\r\n");
+
+                stream.write_all(b"hello").await.unwrap();
+                let len = stream.read(&mut response).await.unwrap();
+                let response = String::from_utf8_lossy(&response[..len]).into_owned();
+                assert!(response.starts_with("HTTP/1.1 200"));
+                assert!(response.ends_with("hello"));
+            },
+        );
+    }
+
+    #[test]
+    fn it_should_close_idle_connections_after_the_keep_alive_timeout() {
+        with_echo_server(
+            |server| server.with_keep_alive_timeout(Duration::from_millis(50)),
+            |addr| async move {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+
+                let read =
+                    tokio::time::timeout(Duration::from_secs(1), stream.read(&mut [0; 16])).await;
+                assert!(matches!(read, Ok(Ok(0))));
+            },
+        );
+    }
+
+    #[test]
+    fn it_should_return_an_error_when_the_address_is_in_use() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = Server::<BasicContext, ()>::new(App::<Request, BasicContext, ()>::new_basic());
+
+        match server.try_start("127.0.0.1", port) {
+            Err(ServerError::AddressInUse { address, .. }) => {
+                assert_eq!(address, format!("127.0.0.1:{}", port))
+            }
+            _ => panic!("expected the address to be in use"),
+        }
+    }
+
+    #[test]
+    fn it_should_finish_requests_in_flight_on_shutdown() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/slow", MiddlewareTuple::A(pinbox!(BasicContext, slow)));
+        let (signal, signalled) = oneshot::channel::<()>();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let server =
+                    tokio::spawn(Server::<BasicContext, ()>::new(app).build_with_shutdown(
+                        "127.0.0.1",
+                        port,
+                        async move {
+                            let _ = signalled.await;
+                        },
+                    ));
+                tokio::time::sleep(Duration::from_millis(50)).await;
+
+                let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+                stream
+                    .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                    .await
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                signal.send(()).unwrap();
+
+                let mut response = vec![0; 1024];
+                let read = stream.read(&mut response).await.unwrap();
+                assert!(String::from_utf8_lossy(&response[..read]).ends_with("slow"));
+
+                server.await.unwrap().unwrap();
+                assert!(TcpStream::connect(("127.0.0.1", port)).await.is_err());
+            });
+    }
+
+    #[test]
+    fn it_should_accept_connections_on_every_listener() {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let listeners = Listeners::bind(&[addr, addr]).unwrap();
+        let addrs = listeners.local_addrs().unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_ne!(addrs[0].port(), 0);
+        assert_ne!(addrs[0], addrs[1]);
+
+        let app = App::<Request, BasicContext, ()>::new_basic()
+            .get("/slow", MiddlewareTuple::A(pinbox!(BasicContext, slow)));
+        let (signal, signalled) = oneshot::channel::<()>();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let server =
+                    tokio::spawn(Server::<BasicContext, ()>::new(app).build_with_listeners(
+                        listeners,
+                        async move {
+                            let _ = signalled.await;
+                        },
+                    ));
+
+                for addr in &addrs {
+                    let mut stream = TcpStream::connect(addr).await.unwrap();
+                    stream
+                        .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                        .await
+                        .unwrap();
+
+                    let mut response = vec![0; 1024];
+                    let read = stream.read(&mut response).await.unwrap();
+                    assert!(String::from_utf8_lossy(&response[..read]).ends_with("slow"));
+                }
+
+                signal.send(()).unwrap();
+                server.await.unwrap().unwrap();
+            });
+    }
+}
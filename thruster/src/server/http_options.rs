@@ -0,0 +1,176 @@
+use futures::{Stream, StreamExt};
+use hyper::server::conn::Http;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::server::listeners::{BindOptions, DEFAULT_BACKLOG};
+
+/// The smallest buffer hyper will read HTTP/1 requests into, so the smallest max header size.
+const MINIMUM_MAX_HEADER_SIZE: usize = 8192;
+
+/// Socket and HTTP options for the hyper servers, set via their `with_http_options`.
+///
+/// ```ignore
+/// let options = HttpOptions::default()
+///     .http2(true)
+///     .http2_keep_alive(Duration::from_secs(20), Duration::from_secs(10))
+///     .max_connections(10_000);
+///
+/// HyperServer::new(app).with_http_options(options)
+/// ```
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+    keep_alive: bool,
+    header_read_timeout: Option<Duration>,
+    max_header_size: Option<usize>,
+    http2: bool,
+    http2_keep_alive: Option<(Duration, Duration)>,
+    pub(crate) nodelay: bool,
+    backlog: i32,
+    max_connections: Option<usize>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            keep_alive: true,
+            header_read_timeout: None,
+            max_header_size: None,
+            http2: false,
+            http2_keep_alive: None,
+            nodelay: true,
+            backlog: DEFAULT_BACKLOG,
+            max_connections: None,
+        }
+    }
+}
+
+impl HttpOptions {
+    /// Sets whether HTTP/1 connections are kept open between requests. Defaults to true.
+    pub fn keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+
+        self
+    }
+
+    /// Closes HTTP/1 connections that don't send the whole of a request's headers within the
+    /// given timeout. Defaults to no timeout.
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = Some(timeout);
+
+        self
+    }
+
+    /// Sets the largest request headers, in bytes, that are accepted. HTTP/1 connections can't
+    /// go below 8KB, so smaller sizes only apply to HTTP/2.
+    pub fn max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = Some(max_header_size);
+
+        self
+    }
+
+    /// Sets whether HTTP/2 is served alongside HTTP/1, both as h2c with prior knowledge and, on
+    /// the TLS server, by offering `h2` via ALPN. Defaults to false.
+    pub fn http2(mut self, http2: bool) -> Self {
+        self.http2 = http2;
+
+        self
+    }
+
+    /// Sends HTTP/2 pings at the given interval, closing the connection if one isn't
+    /// acknowledged within the timeout. Defaults to not sending pings.
+    pub fn http2_keep_alive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.http2_keep_alive = Some((interval, timeout));
+
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on accepted connections. Defaults to true.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+
+        self
+    }
+
+    /// Sets how many connections can be waiting to be accepted when binding to a host and port.
+    /// This doesn't apply to listeners passed to `build_with_listeners`. Defaults to 1024.
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = backlog;
+
+        self
+    }
+
+    /// Sets how many connections can be open at once. Once the limit is reached, the server stops
+    /// accepting connections until one closes. Defaults to no limit.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+
+        self
+    }
+
+    /// The options to bind to a host and port with.
+    pub(crate) fn bind_options(&self) -> BindOptions {
+        BindOptions::default().backlog(self.backlog)
+    }
+
+    /// Creates the hyper connection builder for these options.
+    pub(crate) fn http(&self) -> Http {
+        let mut http = Http::new();
+        http.http1_only(!self.http2)
+            .http1_keep_alive(self.keep_alive);
+
+        if let Some(timeout) = self.header_read_timeout {
+            http.http1_header_read_timeout(timeout);
+        }
+
+        if let Some(max_header_size) = self.max_header_size {
+            http.max_buf_size(max_header_size.max(MINIMUM_MAX_HEADER_SIZE))
+                .http2_max_header_list_size(u32::try_from(max_header_size).unwrap_or(u32::MAX));
+        }
+
+        if let Some((interval, timeout)) = self.http2_keep_alive {
+            http.http2_keep_alive_interval(interval)
+                .http2_keep_alive_timeout(timeout);
+        }
+
+        http
+    }
+
+    /// The protocols to offer via ALPN on TLS connections.
+    #[cfg(feature = "tls")]
+    pub(crate) fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+        if self.http2 {
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        } else {
+            vec![]
+        }
+    }
+
+    pub(crate) fn connection_limit(&self) -> ConnectionLimit {
+        ConnectionLimit(
+            self.max_connections
+                .map(|max_connections| Arc::new(Semaphore::new(max_connections))),
+        )
+    }
+}
+
+/// Limits how many connections a server has open at once, see `HttpOptions::max_connections`.
+pub(crate) struct ConnectionLimit(Option<Arc<Semaphore>>);
+
+impl ConnectionLimit {
+    /// Waits until there's room for another connection, then accepts the next one. The
+    /// connection counts towards the limit until the returned permit is dropped.
+    pub(crate) async fn accept<I: Stream + Unpin>(
+        &self,
+        incoming: &mut I,
+    ) -> Option<(I::Item, Option<OwnedSemaphorePermit>)> {
+        let permit = match &self.0 {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+
+        incoming.next().await.map(|accepted| (accepted, permit))
+    }
+}
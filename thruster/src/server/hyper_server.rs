@@ -1,20 +1,25 @@
 use crate::ReusableBoxFuture;
-use futures::FutureExt;
-use hyper::server::conn::Http;
+use futures::future;
 use hyper::service::Service;
 use hyper::{Body, Request, Response};
-use socket2::{Domain, Socket, Type};
+use std::future::Future;
 use std::net::IpAddr;
-use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
-use std::sync::Arc;
 use std::task::{Context as TaskContext, Poll};
 use tokio::time::{timeout, Duration};
 
 use crate::context::hyper_request::HyperRequest;
 use crate::core::context::Context;
+use crate::core::errors::ServerError;
+use crate::server::http_options::HttpOptions;
+use crate::server::listeners::{bind_address, Listeners};
+use crate::server::shutdown::{accept_until, Shutdown, DEFAULT_SHUTDOWN_TIMEOUT};
+use crate::server::thruster_server::{expect_built, resolve_address};
 use crate::server::ThrusterServer;
-use crate::{app::App, core::request::ThrusterRequest};
+use crate::{
+    app::{App, SwappableApp},
+    core::request::ThrusterRequest,
+};
 
 impl ThrusterRequest for HyperRequest {
     fn method(&self) -> &str {
@@ -28,80 +33,109 @@ impl ThrusterRequest for HyperRequest {
             .map(ToString::to_string)
             .unwrap_or_default()
     }
+
+    fn host(&self) -> Option<&str> {
+        self.request
+            .headers()
+            .get(hyper::header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| self.request.uri().authority().map(|a| a.as_str()))
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.request
+            .headers()
+            .get(name)
+            .and_then(|header| header.to_str().ok())
+    }
 }
 
 pub struct HyperServer<T: 'static + Context + Clone + Send + Sync, S: 'static + Send> {
-    app: App<HyperRequest, T, S>,
+    app: SwappableApp<HyperRequest, T, S>,
     upgrade: bool,
+    options: HttpOptions,
+    shutdown_timeout: Duration,
 }
 
 impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + Send + Sync>
     HyperServer<T, S>
 {
     async fn process(
-        app: Arc<App<HyperRequest, T, S>>,
-        addr: SocketAddr,
+        app: SwappableApp<HyperRequest, T, S>,
+        listeners: Listeners,
         upgrade: bool,
-    ) -> Result<(), hyper::Error> {
-        let listener = {
-            let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
-
-            let address = addr.into();
-            socket.set_reuse_address(true).unwrap();
-            #[cfg(unix)]
-            socket.set_reuse_port(true).unwrap();
-            socket.bind(&address).unwrap();
-            socket.listen(1024).unwrap();
-            socket.set_nonblocking(true).unwrap();
-            let _ = socket.set_nodelay(true);
-
-            let listener: std::net::TcpListener = socket.into();
-            tokio::net::TcpListener::from_std(listener).unwrap()
-        };
+        options: HttpOptions,
+        signal: impl Future<Output = ()>,
+        shutdown_timeout: Duration,
+    ) -> Result<(), ServerError> {
+        let mut incoming = listeners.incoming()?;
+
+        let http = options.http();
+        let connection_limit = options.connection_limit();
 
-        let mut http = Http::new();
-        http.http1_only(true);
+        let shutdown = Shutdown::new();
+        futures::pin_mut!(signal);
 
-        loop {
-            let (stream, _addr) = match listener.accept().await {
+        while let Some(Some((accepted, permit))) =
+            accept_until(connection_limit.accept(&mut incoming), signal.as_mut()).await
+        {
+            let stream = match accepted {
                 Ok(val) => val,
                 _ => break,
             };
+            let _ = stream.set_nodelay(options.nodelay);
 
             let ip = stream.peer_addr().map(|v| v.ip()).ok();
             let arc_app = app.clone();
-            let connection_timeout = arc_app.connection_timeout;
+            let connection_timeout = arc_app.load().connection_timeout;
+            let watch = shutdown.watch();
+            let http = http.clone();
 
             tokio::spawn(async move {
-                let mut http_future =
-                    Http::new().serve_connection(stream, HyperService::<T, S> { ip, app: arc_app });
+                // Counts towards the connection limit until the connection closes
+                let _permit = permit;
+                let http_future =
+                    http.serve_connection(stream, HyperService::<T, S> { ip, app: arc_app });
 
                 if upgrade {
+                    let connection = http_future.with_upgrades();
+                    futures::pin_mut!(connection);
+
                     let _res = timeout(
                         Duration::from_millis(connection_timeout),
-                        &mut http_future.with_upgrades(),
+                        watch.serve(connection, |c| c.graceful_shutdown()),
                     )
                     .await;
                 } else {
-                    let _res =
-                        timeout(Duration::from_millis(connection_timeout), &mut http_future).await;
+                    futures::pin_mut!(http_future);
+
+                    let _res = timeout(
+                        Duration::from_millis(connection_timeout),
+                        watch.serve(http_future, |c| c.graceful_shutdown()),
+                    )
+                    .await;
                 }
             });
         }
 
-        Ok::<_, hyper::Error>(())
+        shutdown.drain(shutdown_timeout).await;
+
+        Ok(())
     }
 
     #[allow(dead_code)]
     pub async fn build_per_thread(self, host: &str, port: u16) {
         // self.app._route_parser.optimize();
 
-        let arc_app = Arc::new(self.app);
+        let arc_app = self.app;
         let addr = (host, port).to_socket_addrs().unwrap().next().unwrap();
         let upgrade = self.upgrade;
+        let options = self.options;
+        let shutdown_timeout = self.shutdown_timeout;
 
         for _ in 0..num_cpus::get() - 1 {
             let arc_app = arc_app.clone();
+            let options = options.clone();
 
             std::thread::spawn(move || {
                 let rt = tokio::runtime::Builder::new_current_thread()
@@ -110,16 +144,35 @@ impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + S
                     .unwrap();
 
                 rt.spawn(async move {
-                    Self::process(arc_app, addr, upgrade)
-                        .await
-                        .expect("Unable to spawn hyper server thread.");
+                    let listeners =
+                        Listeners::from(bind_address(addr, true, options.bind_options()).unwrap());
+
+                    Self::process(
+                        arc_app,
+                        listeners,
+                        upgrade,
+                        options,
+                        future::pending(),
+                        shutdown_timeout,
+                    )
+                    .await
+                    .expect("Unable to spawn hyper server thread.");
                 });
             });
         }
 
-        Self::process(arc_app, addr, upgrade)
-            .await
-            .expect("Unable to spawn hyper server thread.");
+        let listeners = Listeners::from(bind_address(addr, true, options.bind_options()).unwrap());
+
+        Self::process(
+            arc_app,
+            listeners,
+            upgrade,
+            options,
+            future::pending(),
+            shutdown_timeout,
+        )
+        .await
+        .expect("Unable to spawn hyper server thread.");
     }
 }
 
@@ -131,35 +184,100 @@ impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + S
     type Request = HyperRequest;
     type State = S;
 
-    fn new(mut app: App<Self::Request, T, Self::State>) -> Self {
-        app = app.commit();
-
-        HyperServer {
-            app,
-            upgrade: true, // Upgrade is defaulted to true to preserve behavior of older versions
-        }
+    fn new(app: App<Self::Request, T, Self::State>) -> Self {
+        Self::new_swappable(SwappableApp::new(app))
     }
 
     fn build(self, host: &str, port: u16) -> ReusableBoxFuture<()> {
-        let arc_app = Arc::new(self.app);
+        expect_built(self.try_build(host, port))
+    }
 
-        let addr = (host, port).to_socket_addrs().unwrap().next().unwrap();
-        ReusableBoxFuture::new(Self::process(arc_app, addr, self.upgrade).map(|_| ()))
+    fn try_build(self, host: &str, port: u16) -> ReusableBoxFuture<Result<(), ServerError>> {
+        self.build_with_shutdown(host, port, future::pending())
+    }
+
+    fn build_with_shutdown(
+        self,
+        host: &str,
+        port: u16,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
+        // Binds without reuse port, so that a port that's already being served is reported as
+        // in use rather than shared. Only `build_per_thread` shares its port between threads.
+        let listeners = resolve_address(host, port)
+            .and_then(|addr| bind_address(addr, false, self.options.bind_options()))
+            .map(Listeners::from);
+
+        match listeners {
+            Ok(listeners) => self.build_with_listeners(listeners, signal),
+            Err(e) => ReusableBoxFuture::new(future::ready(Err(e))),
+        }
     }
 }
 
 impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + Send + Sync>
     HyperServer<T, S>
 {
+    /// Creates a server for an app that can be swapped out while the server is running.
+    pub fn new_swappable(app: SwappableApp<HyperRequest, T, S>) -> Self {
+        HyperServer {
+            app,
+            upgrade: true, // Upgrade is defaulted to true to preserve behavior of older versions
+            options: HttpOptions::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    /// Builds the server like `build_with_shutdown`, but accepts connections on the given
+    /// listeners rather than binding to a host and port.
+    pub fn build_with_listeners(
+        self,
+        listeners: Listeners,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
+        let arc_app = self.app;
+        let upgrade = self.upgrade;
+        let options = self.options;
+        let shutdown_timeout = self.shutdown_timeout;
+
+        ReusableBoxFuture::new(async move {
+            Self::process(
+                arc_app,
+                listeners,
+                upgrade,
+                options,
+                signal,
+                shutdown_timeout,
+            )
+            .await
+        })
+    }
+
     pub fn with_upgrades(mut self, upgrade: bool) -> Self {
         self.upgrade = upgrade;
 
         self
     }
+
+    /// Sets the socket and HTTP options, such as keep-alive, HTTP/2 support and the connection
+    /// limit, that the server's connections are served with.
+    pub fn with_http_options(mut self, options: HttpOptions) -> Self {
+        self.options = options;
+
+        self
+    }
+
+    /// Sets how long `build_with_shutdown` waits for open connections to finish after the
+    /// shutdown signal. Defaults to 30 seconds.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+
+        self
+    }
 }
 
 pub(crate) struct HyperService<T: 'static + Context + Clone + Send + Sync, S: Send> {
-    pub(crate) app: Arc<App<HyperRequest, T, S>>,
+    pub(crate) app: SwappableApp<HyperRequest, T, S>,
     pub(crate) ip: Option<IpAddr>,
 }
 
@@ -178,6 +296,188 @@ impl<T: 'static + Context + Clone + Send + Sync, S: 'static + Send + Sync> Servi
         let mut req = HyperRequest::new(req);
         req.ip = self.ip;
 
-        self.app.clone().match_and_resolve(req)
+        self.app.load().match_and_resolve(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::basic_hyper_context::{generate_context, BasicHyperContext};
+    use crate::core::errors::ThrusterError;
+    use crate::parser::middleware_traits::{MiddlewareTuple, NextFn};
+    use crate::pinbox;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
+    use tokio::task::JoinHandle;
+
+    async fn hello(
+        mut context: BasicHyperContext,
+        _next: NextFn<BasicHyperContext>,
+    ) -> Result<BasicHyperContext, ThrusterError<BasicHyperContext>> {
+        context.body("hello");
+        Ok(context)
+    }
+
+    /// Starts a server with the given options, giving its address, the sender to shut it down
+    /// with and the server's task.
+    fn start(
+        options: HttpOptions,
+    ) -> (
+        SocketAddr,
+        oneshot::Sender<()>,
+        JoinHandle<Result<(), ServerError>>,
+    ) {
+        let listeners = Listeners::bind(&["127.0.0.1:0".parse().unwrap()]).unwrap();
+        let addr = listeners.local_addrs().unwrap()[0];
+        let app = App::<HyperRequest, BasicHyperContext, ()>::create(generate_context, ())
+            .get("/", MiddlewareTuple::A(pinbox!(BasicHyperContext, hello)));
+        let (signal, signalled) = oneshot::channel::<()>();
+
+        let server = tokio::spawn(
+            HyperServer::new(app)
+                .with_http_options(options)
+                .build_with_listeners(listeners, async move {
+                    let _ = signalled.await;
+                }),
+        );
+
+        (addr, signal, server)
+    }
+
+    /// Sends the HTTP/2 connection preface, giving the type of the first frame sent back, if any.
+    async fn first_http2_frame(options: HttpOptions) -> Option<u8> {
+        let (addr, signal, server) = start(options);
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\0\0\0\x04\0\0\0\0\0")
+            .await
+            .unwrap();
+
+        let mut frame = vec![0; 9];
+        let frame_type = match stream.read_exact(&mut frame).await {
+            Ok(_) if !frame.starts_with(b"HTTP/1") => Some(frame[3]),
+            _ => None,
+        };
+
+        drop(stream);
+        signal.send(()).unwrap();
+        server.await.unwrap().unwrap();
+
+        frame_type
+    }
+
+    /// Sends a `GET /` with a header of the given length, giving the start of the response.
+    async fn get(stream: &mut TcpStream, header_len: usize) -> String {
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nX-Padding: {}\r\n\r\n",
+            "a".repeat(header_len)
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = vec![0; 1024];
+        let read = stream.read(&mut response).await.unwrap();
+
+        String::from_utf8_lossy(&response[..read]).into_owned()
+    }
+
+    #[test]
+    fn it_should_only_serve_http2_when_enabled() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                // The server's SETTINGS frame
+                assert_eq!(
+                    first_http2_frame(HttpOptions::default().http2(true)).await,
+                    Some(0x4)
+                );
+                assert_eq!(first_http2_frame(HttpOptions::default()).await, None);
+            });
+    }
+
+    #[test]
+    fn it_should_return_an_error_when_the_address_is_in_use() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let app = || App::<HyperRequest, BasicHyperContext, ()>::create(generate_context, ());
+        let (signal, signalled) = oneshot::channel::<()>();
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let server = tokio::spawn(HyperServer::new(app()).build_with_shutdown(
+                    "127.0.0.1",
+                    port,
+                    async move {
+                        let _ = signalled.await;
+                    },
+                ));
+                tokio::time::sleep(Duration::from_millis(50)).await;
+
+                // Times out rather than hanging if the second server shares the port instead
+                let second = HyperServer::new(app()).try_build("127.0.0.1", port);
+                match tokio::time::timeout(Duration::from_secs(1), second).await {
+                    Ok(Err(ServerError::AddressInUse { address, .. })) => {
+                        assert_eq!(address, format!("127.0.0.1:{}", port))
+                    }
+                    _ => panic!("expected the address to be in use"),
+                }
+
+                signal.send(()).unwrap();
+                server.await.unwrap().unwrap();
+            });
+    }
+
+    #[test]
+    fn it_should_wait_for_a_connection_to_close_once_at_the_limit() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (addr, signal, server) = start(HttpOptions::default().max_connections(1));
+
+                let mut first = TcpStream::connect(addr).await.unwrap();
+                assert!(get(&mut first, 0).await.ends_with("hello"));
+
+                // The second connection isn't accepted while the first is kept alive
+                let mut second = TcpStream::connect(addr).await.unwrap();
+                let waiting =
+                    tokio::time::timeout(Duration::from_millis(100), get(&mut second, 0)).await;
+                assert!(waiting.is_err());
+
+                drop(first);
+                let mut response = vec![0; 1024];
+                let read = second.read(&mut response).await.unwrap();
+                assert!(String::from_utf8_lossy(&response[..read]).ends_with("hello"));
+
+                drop(second);
+                signal.send(()).unwrap();
+                server.await.unwrap().unwrap();
+            });
+    }
+
+    #[test]
+    fn it_should_raise_small_max_header_sizes_to_the_minimum_for_http1() {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let (addr, signal, server) = start(HttpOptions::default().max_header_size(1024));
+
+                // Over the configured size, but under the 8KB that HTTP/1 can't go below
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                assert!(get(&mut stream, 4096).await.starts_with("HTTP/1.1 200"));
+
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                assert!(!get(&mut stream, 16384).await.starts_with("HTTP/1.1 200"));
+
+                drop(stream);
+                signal.send(()).unwrap();
+                server.await.unwrap().unwrap();
+            });
     }
 }
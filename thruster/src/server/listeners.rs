@@ -0,0 +1,181 @@
+use futures::stream::{self, Stream};
+use socket2::{Domain, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use tokio::net::TcpStream;
+use tokio_stream::wrappers::TcpListenerStream;
+
+use crate::core::errors::ServerError;
+
+/// How many connections can be waiting to be accepted, unless set otherwise.
+pub(crate) const DEFAULT_BACKLOG: i32 = 1024;
+
+/// Options for binding `Listeners` to addresses, see `Listeners::bind_with_options`.
+///
+/// ```ignore
+/// let options = BindOptions::default().only_v6(true).backlog(4096);
+/// let listeners = Listeners::bind_with_options(
+///     &["0.0.0.0:8080".parse()?, "[::]:8080".parse()?],
+///     options,
+/// )?;
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct BindOptions {
+    backlog: i32,
+    only_v6: Option<bool>,
+}
+
+impl Default for BindOptions {
+    fn default() -> Self {
+        BindOptions {
+            backlog: DEFAULT_BACKLOG,
+            only_v6: None,
+        }
+    }
+}
+
+impl BindOptions {
+    /// Sets how many connections can be waiting to be accepted on each listener. Defaults to
+    /// 1024.
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = backlog;
+
+        self
+    }
+
+    /// Sets whether listeners bound to IPv6 addresses only accept IPv6 connections, rather than
+    /// IPv4 ones too. This is needed to bind an IPv4 and an IPv6 address on the same port, e.g.
+    /// `0.0.0.0:80` and `[::]:80`. Defaults to the OS's setting, which on most is to accept both.
+    pub fn only_v6(mut self, only_v6: bool) -> Self {
+        self.only_v6 = Some(only_v6);
+
+        self
+    }
+}
+
+/// The TCP listeners a server accepts connections on, for listening on several addresses with
+/// one app, or on listeners that were bound elsewhere, e.g. by systemd socket activation. See
+/// the `build_with_listeners` method of the TCP servers.
+///
+/// ```ignore
+/// let listeners = Listeners::bind(&["127.0.0.1:0".parse()?, "[::1]:0".parse()?])?;
+/// let addrs = listeners.local_addrs()?;
+///
+/// HyperServer::new(app).build_with_listeners(listeners, shutdown_signal)
+/// ```
+#[derive(Debug, Default)]
+pub struct Listeners {
+    listeners: Vec<TcpListener>,
+}
+
+impl Listeners {
+    /// Binds a listener to each of the given addresses with the default `BindOptions`. Whether
+    /// a listener bound to an IPv6 address, e.g. `[::]`, also accepts IPv4 connections is left
+    /// to the OS, see `BindOptions::only_v6`.
+    pub fn bind(addrs: &[SocketAddr]) -> Result<Listeners, ServerError> {
+        Listeners::bind_with_options(addrs, BindOptions::default())
+    }
+
+    /// Binds a listener to each of the given addresses with the given options.
+    pub fn bind_with_options(
+        addrs: &[SocketAddr],
+        options: BindOptions,
+    ) -> Result<Listeners, ServerError> {
+        let listeners = addrs
+            .iter()
+            .map(|addr| bind_address(*addr, false, options))
+            .collect::<Result<Vec<TcpListener>, ServerError>>()?;
+
+        Ok(Listeners { listeners })
+    }
+
+    /// Adds a listener that's already bound.
+    pub fn with_listener(mut self, listener: TcpListener) -> Self {
+        self.listeners.push(listener);
+
+        self
+    }
+
+    /// Gets the addresses that the listeners are bound to, e.g. to find out which port was picked
+    /// when binding to port `0`.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, ServerError> {
+        self.listeners
+            .iter()
+            .map(|listener| listener.local_addr().map_err(ServerError::Io))
+            .collect()
+    }
+
+    /// Turns the listeners into a stream of the connections accepted on any of them. This has to
+    /// be called from within a tokio runtime.
+    pub(crate) fn incoming(
+        self,
+    ) -> Result<impl Stream<Item = io::Result<TcpStream>> + Unpin, ServerError> {
+        let streams = self
+            .listeners
+            .into_iter()
+            .map(|listener| {
+                listener.set_nonblocking(true)?;
+
+                Ok(TcpListenerStream::new(tokio::net::TcpListener::from_std(
+                    listener,
+                )?))
+            })
+            .collect::<io::Result<Vec<TcpListenerStream>>>()?;
+
+        Ok(stream::select_all(streams))
+    }
+}
+
+impl From<TcpListener> for Listeners {
+    fn from(listener: TcpListener) -> Self {
+        Listeners::default().with_listener(listener)
+    }
+}
+
+/// Binds a listener to the given address, optionally letting other sockets bind to the same
+/// port so that several threads can each accept on their own listener.
+pub(crate) fn bind_address(
+    addr: SocketAddr,
+    reuse_port: bool,
+    options: BindOptions,
+) -> Result<TcpListener, ServerError> {
+    let bind = || -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+
+        if let (true, Some(only_v6)) = (addr.is_ipv6(), options.only_v6) {
+            socket.set_only_v6(only_v6)?;
+        }
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        if reuse_port {
+            socket.set_reuse_port(true)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(options.backlog)?;
+        let _ = socket.set_nodelay(true);
+
+        Ok(socket.into())
+    };
+
+    bind().map_err(|e| ServerError::bind(addr, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_bind_ipv4_and_ipv6_on_the_same_port_when_only_v6() {
+        let v4 = Listeners::bind(&["0.0.0.0:0".parse().unwrap()]).unwrap();
+        let port = v4.local_addrs().unwrap()[0].port();
+
+        let v6 = Listeners::bind_with_options(
+            &[SocketAddr::from(([0u16; 8], port))],
+            BindOptions::default().only_v6(true),
+        )
+        .unwrap();
+
+        assert_eq!(v6.local_addrs().unwrap()[0].port(), port);
+    }
+}
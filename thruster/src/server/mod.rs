@@ -13,8 +13,15 @@ pub mod ssl_hyper_server;
 #[cfg(feature = "actix_server")]
 pub mod actix_server;
 
+#[cfg(feature = "hyper_server")]
+mod http_options;
+mod listeners;
+mod shutdown;
 mod thruster_server;
 
+#[cfg(feature = "hyper_server")]
+pub use http_options::HttpOptions;
+pub use listeners::{BindOptions, Listeners};
 pub use thruster_server::ThrusterServer;
 
 #[cfg(feature = "hyper_server")]
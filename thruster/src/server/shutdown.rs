@@ -0,0 +1,107 @@
+use futures::future::{self, Either};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+/// How long servers wait for open connections to finish after a shutdown signal, unless set via
+/// their `with_shutdown_timeout`.
+pub(crate) const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tracks the connections a server has open so that, once the server stops accepting new ones,
+/// it can tell the open ones to finish up and wait for them to close.
+pub(crate) struct Shutdown {
+    signalled: watch::Sender<bool>,
+    watch: Watch,
+    closed: mpsc::Receiver<()>,
+}
+
+/// Held by each open connection, to find out when the server is shutting down.
+#[derive(Clone)]
+pub(crate) struct Watch {
+    signalled: watch::Receiver<bool>,
+    _open: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    pub(crate) fn new() -> Shutdown {
+        let (signalled, signalled_receiver) = watch::channel(false);
+        let (open, closed) = mpsc::channel(1);
+
+        Shutdown {
+            signalled,
+            watch: Watch {
+                signalled: signalled_receiver,
+                _open: open,
+            },
+            closed,
+        }
+    }
+
+    /// Gets a watch for a newly opened connection.
+    pub(crate) fn watch(&self) -> Watch {
+        self.watch.clone()
+    }
+
+    /// Signals the open connections to finish up, and waits up to the given timeout for them
+    /// to close.
+    pub(crate) async fn drain(self, timeout: Duration) {
+        let Shutdown {
+            signalled,
+            watch,
+            mut closed,
+        } = self;
+
+        let _ = signalled.send(true);
+        drop(watch);
+
+        // Every watch holds a sender, so this only resolves once they've all been dropped
+        let _ = tokio::time::timeout(timeout, closed.recv()).await;
+    }
+}
+
+impl Watch {
+    /// Resolves once the server is shutting down.
+    pub(crate) async fn signalled(&mut self) {
+        while !*self.signalled.borrow() {
+            if self.signalled.changed().await.is_err() {
+                // The server was dropped without draining, so there's nothing to wait for
+                future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Drives a connection to completion, calling `graceful_shutdown` on it once the server is
+    /// shutting down so that it finishes the request in flight rather than waiting for more.
+    #[cfg(feature = "hyper_server")]
+    pub(crate) async fn serve<C: Future>(
+        mut self,
+        mut connection: Pin<&mut C>,
+        graceful_shutdown: impl FnOnce(Pin<&mut C>),
+    ) -> C::Output {
+        let signalled = self.signalled();
+        futures::pin_mut!(signalled);
+
+        match future::select(connection.as_mut(), signalled).await {
+            Either::Left((output, _)) => output,
+            Either::Right(_) => {
+                graceful_shutdown(connection.as_mut());
+
+                connection.await
+            }
+        }
+    }
+}
+
+/// Resolves to the next accepted connection, or `None` once the shutdown signal resolves.
+pub(crate) async fn accept_until<A: Future>(
+    accept: A,
+    signal: Pin<&mut impl Future<Output = ()>>,
+) -> Option<A::Output> {
+    futures::pin_mut!(accept);
+
+    match future::select(accept, signal).await {
+        Either::Left((accepted, _)) => Some(accepted),
+        Either::Right(_) => None,
+    }
+}
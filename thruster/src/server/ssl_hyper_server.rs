@@ -2,39 +2,58 @@ use async_trait::async_trait;
 use futures::future;
 use futures::stream::StreamExt;
 use futures::FutureExt;
-use hyper::server::conn::Http;
 use hyper::{Body, Response};
 use log::error;
 use pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
 use rustls_pemfile::{certs, pkcs8_private_keys};
-use std::io::{self, BufReader};
-use std::net::ToSocketAddrs;
+use std::future::Future;
+use std::io::BufReader;
 use std::sync::Arc;
-use tokio::net::TcpListener;
 use tokio::time::{timeout, Duration};
 use tokio_rustls::rustls::ServerConfig;
 use tokio_rustls::TlsAcceptor;
-use tokio_stream::wrappers::TcpListenerStream;
 use tokio_util::sync::ReusableBoxFuture;
 
-use crate::app::App;
+use crate::app::{App, SwappableApp};
 use crate::context::basic_hyper_context::HyperRequest;
 use crate::core::context::Context;
+use crate::core::errors::ServerError;
 
 use crate::hyper_server::HyperService;
+use crate::server::http_options::HttpOptions;
+use crate::server::listeners::{bind_address, Listeners};
+use crate::server::shutdown::{accept_until, Shutdown, DEFAULT_SHUTDOWN_TIMEOUT};
+use crate::server::thruster_server::{expect_built, resolve_address};
 use crate::server::ThrusterServer;
 
 /// Fake certs generated using
 /// openssl req -x509 -newkey rsa:4096 -keyout key.pem -out cert.pem -days 3650 -nodes
 pub struct SSLHyperServer<T: 'static + Context + Clone + Send + Sync, S: Send> {
-    app: App<HyperRequest, T, S>,
+    app: SwappableApp<HyperRequest, T, S>,
     cert: Option<Vec<u8>>,
     key: Option<Vec<u8>>,
     tls_acceptor: Option<Arc<TlsAcceptor>>,
     upgrade: bool,
+    options: HttpOptions,
+    shutdown_timeout: Duration,
 }
 
 impl<T: 'static + Context + Clone + Send + Sync, S: Send> SSLHyperServer<T, S> {
+    ///
+    /// Creates a server for an app that can be swapped out while the server is running
+    ///
+    pub fn new_swappable(app: SwappableApp<HyperRequest, T, S>) -> Self {
+        SSLHyperServer {
+            app,
+            cert: None,
+            key: None,
+            tls_acceptor: None,
+            upgrade: true,
+            options: HttpOptions::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+
     ///
     /// Sets the cert on the server
     ///
@@ -46,6 +65,14 @@ impl<T: 'static + Context + Clone + Send + Sync, S: Send> SSLHyperServer<T, S> {
     pub fn key(&mut self, key: Vec<u8>) {
         self.key = Some(key);
     }
+
+    /// Sets the socket and HTTP options, such as keep-alive, HTTP/2 support and the connection
+    /// limit, that the server's connections are served with.
+    pub fn with_http_options(mut self, options: HttpOptions) -> Self {
+        self.options = options;
+
+        self
+    }
 }
 
 #[async_trait]
@@ -57,53 +84,68 @@ impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + S
     type Request = HyperRequest;
     type State = S;
 
-    fn new(mut app: App<Self::Request, T, Self::State>) -> Self {
-        app = app.commit();
+    fn new(app: App<Self::Request, T, Self::State>) -> Self {
+        Self::new_swappable(SwappableApp::new(app))
+    }
 
-        SSLHyperServer {
-            app,
-            cert: None,
-            key: None,
-            tls_acceptor: None,
-            upgrade: true,
+    fn build(self, host: &str, port: u16) -> ReusableBoxFuture<()> {
+        expect_built(self.try_build(host, port))
+    }
+
+    fn try_build(self, host: &str, port: u16) -> ReusableBoxFuture<Result<(), ServerError>> {
+        self.build_with_shutdown(host, port, future::pending())
+    }
+
+    fn build_with_shutdown(
+        self,
+        host: &str,
+        port: u16,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
+        let listeners = resolve_address(host, port)
+            .and_then(|addr| bind_address(addr, false, self.options.bind_options()))
+            .map(Listeners::from);
+
+        match listeners {
+            Ok(listeners) => self.build_with_listeners(listeners, signal),
+            Err(e) => ReusableBoxFuture::new(future::ready(Err(e))),
         }
     }
+}
 
-    fn build(mut self, host: &str, port: u16) -> ReusableBoxFuture<()> {
-        let addr = (host, port).to_socket_addrs().unwrap().next().unwrap();
+impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + Send + Sync>
+    SSLHyperServer<T, S>
+{
+    /// Builds the server like `build_with_shutdown`, but accepts connections on the given
+    /// listeners rather than binding to a host and port.
+    pub fn build_with_listeners(
+        mut self,
+        listeners: Listeners,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
         let upgrade = self.upgrade;
-        let arc_app = Arc::new(self.app);
-
-        let cert_u8: &[u8] = &self.cert.unwrap();
-        let key_u8: &[u8] = &self.key.unwrap();
-        let certs = certs(&mut BufReader::new(cert_u8))
-            .expect("Could not read certs passed in")
-            .into_iter()
-            .map(Into::into)
-            .collect();
-        let mut key: PrivateKeyDer<'static> = pkcs8_private_keys(&mut BufReader::new(key_u8))
-            .expect("Could not read private keys passed in")
-            .into_iter()
-            .next()
-            .map(PrivatePkcs8KeyDer::from)
-            .map(Into::into)
-            .expect("Could not form private keys passed in");
-        let config = ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, key)
-            .expect("Bad certificates");
+        let shutdown_timeout = self.shutdown_timeout;
+        let arc_app = self.app;
+        let http = self.options.http();
+        let connection_limit = self.options.connection_limit();
+        let nodelay = self.options.nodelay;
+
+        let mut config = match tls_config(self.cert.as_deref(), self.key.as_deref()) {
+            Ok(config) => config,
+            Err(e) => return ReusableBoxFuture::new(future::ready(Err(e))),
+        };
+        config.alpn_protocols = self.options.alpn_protocols();
 
         self.tls_acceptor = Some(Arc::new(TlsAcceptor::from(Arc::new(config))));
 
         let arc_acceptor = self.tls_acceptor.as_ref().unwrap().clone();
-        let listener_fut = TcpListener::bind(addr).then(move |listener| {
-            let arc_acceptor = arc_acceptor.clone();
-
-            let stream = TcpListenerStream::new(listener.unwrap());
+        ReusableBoxFuture::new(async move {
+            let stream = listeners.incoming()?;
 
             let mut hyper_stream = stream.filter_map(move |socket| match socket {
                 Ok(stream) => {
                     let acceptor = arc_acceptor.clone();
+                    let _ = stream.set_nodelay(nodelay);
 
                     let timed_out_fut = acceptor.accept(stream).map(|timed_out| match timed_out {
                         Ok(val) => Some(Ok::<_, std::io::Error>(val)),
@@ -121,50 +163,86 @@ impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + S
                 }
             });
 
-            async move {
-                loop {
-                    let stream = match hyper_stream.next().await {
-                        Some(Ok(val)) => val,
-                        _ => {
-                            continue;
-                        }
-                    };
-
-                    // let ip = stream.peer_addr().map(|v| v.ip()).ok();
-                    let arc_app = arc_app.clone();
-                    let connection_timeout = arc_app.connection_timeout;
-
-                    tokio::spawn(async move {
-                        let mut http_future = Http::new().serve_connection(
-                            stream,
-                            HyperService::<T, S> {
-                                ip: None,
-                                app: arc_app,
-                            },
-                        );
-
-                        if upgrade {
-                            let _res = timeout(
-                                Duration::from_millis(connection_timeout),
-                                &mut http_future.with_upgrades(),
-                            )
-                            .await;
-                        } else {
-                            let _res = timeout(
-                                Duration::from_millis(connection_timeout),
-                                &mut http_future,
-                            )
-                            .await;
-                        }
-                    });
-                }
+            let shutdown = Shutdown::new();
+            futures::pin_mut!(signal);
+
+            while let Some(accepted) =
+                accept_until(connection_limit.accept(&mut hyper_stream), signal.as_mut()).await
+            {
+                let (stream, permit) = match accepted {
+                    Some((Ok(val), permit)) => (val, permit),
+                    Some((Err(_), _)) => continue,
+                    None => break,
+                };
+
+                // let ip = stream.peer_addr().map(|v| v.ip()).ok();
+                let arc_app = arc_app.clone();
+                let connection_timeout = arc_app.load().connection_timeout;
+                let watch = shutdown.watch();
+                let http = http.clone();
+
+                tokio::spawn(async move {
+                    // Counts towards the connection limit until the connection closes
+                    let _permit = permit;
+                    let http_future = http.serve_connection(
+                        stream,
+                        HyperService::<T, S> {
+                            ip: None,
+                            app: arc_app,
+                        },
+                    );
+
+                    if upgrade {
+                        let connection = http_future.with_upgrades();
+                        futures::pin_mut!(connection);
+
+                        let _res = timeout(
+                            Duration::from_millis(connection_timeout),
+                            watch.serve(connection, |c| c.graceful_shutdown()),
+                        )
+                        .await;
+                    } else {
+                        futures::pin_mut!(http_future);
+
+                        let _res = timeout(
+                            Duration::from_millis(connection_timeout),
+                            watch.serve(http_future, |c| c.graceful_shutdown()),
+                        )
+                        .await;
+                    }
+                });
             }
-        });
 
-        ReusableBoxFuture::new(listener_fut)
+            shutdown.drain(shutdown_timeout).await;
+
+            Ok(())
+        })
     }
 }
 
+/// Loads the cert and key into a rustls config.
+fn tls_config(cert: Option<&[u8]>, key: Option<&[u8]>) -> Result<ServerConfig, ServerError> {
+    let cert_u8 = cert.ok_or_else(|| ServerError::Tls("No cert was set".to_owned()))?;
+    let key_u8 = key.ok_or_else(|| ServerError::Tls("No key was set".to_owned()))?;
+    let certs = certs(&mut BufReader::new(cert_u8))
+        .map_err(|e| ServerError::Tls(format!("Could not read certs passed in: {}", e)))?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let key: PrivateKeyDer<'static> = pkcs8_private_keys(&mut BufReader::new(key_u8))
+        .map_err(|e| ServerError::Tls(format!("Could not read private keys passed in: {}", e)))?
+        .into_iter()
+        .next()
+        .map(PrivatePkcs8KeyDer::from)
+        .map(Into::into)
+        .ok_or_else(|| ServerError::Tls("Could not form private keys passed in".to_owned()))?;
+
+    ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| ServerError::Tls(format!("Bad certificates: {}", e)))
+}
+
 impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + Send + Sync>
     SSLHyperServer<T, S>
 {
@@ -173,4 +251,12 @@ impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + S
 
         self
     }
+
+    /// Sets how long `build_with_shutdown` waits for open connections to finish after the
+    /// shutdown signal. Defaults to 30 seconds.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+
+        self
+    }
 }
@@ -1,4 +1,10 @@
+use futures::future::{self, Either};
+use futures::FutureExt;
+use std::future::Future;
+use std::net::{SocketAddr, ToSocketAddrs};
+
 use crate::core::context::Context;
+use crate::core::errors::ServerError;
 use crate::ReusableBoxFuture;
 use crate::{app::App, core::request::ThrusterRequest};
 
@@ -18,4 +24,87 @@ pub trait ThrusterServer {
             .unwrap()
             .block_on(self.build(host, port))
     }
+
+    /// Creates the server like `new`, but returns an error rather than panicking if the app's
+    /// routes are malformed or conflict, see `App::validate`.
+    fn try_new(app: App<Self::Request, Self::Context, Self::State>) -> Result<Self, ServerError>
+    where
+        Self: Sized,
+        Self::Request: 'static,
+        Self::State: 'static,
+    {
+        app.validate()?;
+
+        Ok(Self::new(app))
+    }
+
+    /// Builds the server like `build`, but the future resolves to an error rather than panicking
+    /// if the server can't start, e.g. because the address is already in use.
+    fn try_build(self, host: &str, port: u16) -> ReusableBoxFuture<Result<(), ServerError>>
+    where
+        Self: Sized,
+    {
+        ReusableBoxFuture::new(self.build(host, port).map(Ok))
+    }
+
+    /// Builds the server like `try_build`, but once the given signal resolves, e.g. on a
+    /// `SIGTERM`, the server stops accepting connections and waits for the requests in flight to
+    /// finish, up to its shutdown timeout, before the future resolves.
+    ///
+    /// The default implementation stops accepting connections on the signal without waiting for
+    /// the ones that are open.
+    fn build_with_shutdown(
+        self,
+        host: &str,
+        port: u16,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>>
+    where
+        Self: Sized,
+    {
+        let built = self.try_build(host, port);
+
+        ReusableBoxFuture::new(async move {
+            match future::select(built, Box::pin(signal)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Ok(()),
+            }
+        })
+    }
+
+    /// Starts the server like `start`, but returns an error rather than panicking if the server
+    /// can't start.
+    fn try_start(self, host: &str, port: u16) -> Result<(), ServerError>
+    where
+        Self: Sized,
+    {
+        tokio::runtime::Runtime::new()?.block_on(self.try_build(host, port))
+    }
+}
+
+/// Resolves the host and port to the first address they refer to.
+pub(crate) fn resolve_address(host: &str, port: u16) -> Result<SocketAddr, ServerError> {
+    let address = format!("{}:{}", host, port);
+
+    match (host, port).to_socket_addrs() {
+        Ok(mut addresses) => addresses.next().ok_or(ServerError::InvalidAddress {
+            address,
+            source: None,
+        }),
+        Err(e) => Err(ServerError::InvalidAddress {
+            address,
+            source: Some(e),
+        }),
+    }
+}
+
+/// Turns the future of a `try_build` into that of a `build`, which panics on errors.
+pub(crate) fn expect_built(
+    built: ReusableBoxFuture<Result<(), ServerError>>,
+) -> ReusableBoxFuture<()> {
+    ReusableBoxFuture::new(built.map(|result| {
+        if let Err(e) = result {
+            panic!("{}", e);
+        }
+    }))
 }
@@ -1,19 +1,46 @@
+use futures::future::{self, Either};
 use futures::FutureExt;
 use hyper::service::make_service_fn;
 use hyper::{Body, Response, Server};
 use hyperlocal::UnixServerExt;
-use std::sync::Arc;
+use std::future::Future;
+use std::time::Duration;
 use std::{fs, path::Path};
+use tokio::sync::oneshot;
 use tokio_util::sync::ReusableBoxFuture;
 
-use crate::app::App;
+use crate::app::{App, SwappableApp};
 use crate::context::basic_hyper_context::HyperRequest;
 use crate::core::context::Context;
+use crate::core::errors::ServerError;
 use crate::server::hyper_server::HyperService;
+use crate::server::shutdown::DEFAULT_SHUTDOWN_TIMEOUT;
+use crate::server::thruster_server::expect_built;
 use crate::server::ThrusterServer;
 
 pub struct UnixHyperServer<T: 'static + Context + Clone + Send + Sync, S: Send> {
-    app: Arc<App<HyperRequest, T, S>>,
+    app: SwappableApp<HyperRequest, T, S>,
+    shutdown_timeout: Duration,
+}
+
+impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + Send + Sync>
+    UnixHyperServer<T, S>
+{
+    /// Creates a server for an app that can be swapped out while the server is running.
+    pub fn new_swappable(app: SwappableApp<HyperRequest, T, S>) -> Self {
+        UnixHyperServer {
+            app,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    /// Sets how long `build_with_shutdown` waits for open connections to finish after the
+    /// shutdown signal. Defaults to 30 seconds.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+
+        self
+    }
 }
 
 impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + Send + Sync>
@@ -24,19 +51,39 @@ impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + S
     type Request = HyperRequest;
     type State = S;
 
-    fn new(mut app: App<Self::Request, T, Self::State>) -> Self {
-        app = app.commit();
-
-        UnixHyperServer { app: Arc::new(app) }
+    fn new(app: App<Self::Request, T, Self::State>) -> Self {
+        Self::new_swappable(SwappableApp::new(app))
     }
 
     fn build(self, socket_path: &str, _unused_port: u16) -> ReusableBoxFuture<()> {
+        expect_built(self.try_build(socket_path, _unused_port))
+    }
+
+    fn try_build(
+        self,
+        socket_path: &str,
+        _unused_port: u16,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
+        self.build_with_shutdown(socket_path, _unused_port, future::pending())
+    }
+
+    fn build_with_shutdown(
+        self,
+        socket_path: &str,
+        _unused_port: u16,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> ReusableBoxFuture<Result<(), ServerError>> {
         let app = self.app;
+        let shutdown_timeout = self.shutdown_timeout;
         let path = Path::new(socket_path);
 
         if path.exists() {
-            fs::remove_file(path)
-                .unwrap_or_else(|_| panic!("Could not remove file: {}", socket_path));
+            if let Err(e) = fs::remove_file(path) {
+                return ReusableBoxFuture::new(future::ready(Err(ServerError::bind(
+                    socket_path,
+                    e,
+                ))));
+            }
         }
 
         let service = make_service_fn(move |_: &tokio::net::UnixStream| {
@@ -50,14 +97,39 @@ impl<T: Context<Response = Response<Body>> + Clone + Send + Sync, S: 'static + S
             }
         });
 
-        let listener_fut = Server::bind_unix(path)
-            .unwrap()
+        let builder = match Server::bind_unix(path) {
+            Ok(builder) => builder,
+            Err(e) => {
+                return ReusableBoxFuture::new(future::ready(Err(ServerError::bind(
+                    socket_path,
+                    e,
+                ))))
+            }
+        };
+
+        // Hyper waits for the open connections itself, so only the deadline is needed here
+        let (signalled, deadline) = oneshot::channel();
+        let listener_fut = builder
             .serve(service)
-            .map(|v| match v {
-                Ok(_) => (),
-                Err(e) => panic!("Hyper server error occurred: {:#?}", e),
-            });
+            .with_graceful_shutdown(async move {
+                signal.await;
+                let _ = signalled.send(());
+            })
+            .map(|v| v.map_err(|e| ServerError::Io(std::io::Error::other(e))));
+        let deadline = async move {
+            match deadline.await {
+                Ok(()) => tokio::time::sleep(shutdown_timeout).await,
+                Err(_) => future::pending().await,
+            }
+        };
 
-        ReusableBoxFuture::new(listener_fut)
+        ReusableBoxFuture::new(async move {
+            futures::pin_mut!(listener_fut, deadline);
+
+            match future::select(listener_fut, deadline).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => Ok(()),
+            }
+        })
     }
 }